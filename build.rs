@@ -1,7 +1,7 @@
 use elf::{abi::*, endian::AnyEndian, ElfBytes};
-use std::{env, process::Command};
+use std::{env, path::Path, process::Command};
 
-const TARGETS: &[&str] = &["x86_64-unknown-none"];
+const TARGETS: &[&str] = &["x86_64-unknown-none", "aarch64-unknown-none", "riscv64gc-unknown-none-elf", "i686-unknown-none"];
 
 const DT_RELR: i64 = 36;
 
@@ -30,9 +30,16 @@ fn main() {
     println!("cargo::rerun-if-changed=shim");
 
     let out_dir = env::var("OUT_DIR").unwrap();
-    let call_rustc = |target| {
-        let status = Command::new("rustc")
-            .arg(format!("--target={target}"))
+    let call_rustc = |target: &str| {
+        // Targets that rustc does not ship with are described by a target specification next to the shim.
+        let target_spec = format!("shim/{target}.json");
+        let mut rustc = Command::new("rustc");
+        if Path::new(&target_spec).exists() {
+            rustc.arg("-Zunstable-options").arg(format!("--target={target_spec}"));
+        } else {
+            rustc.arg(format!("--target={target}"));
+        }
+        let status = rustc
             .arg("-Copt-level=s")
             .arg("-Cpanic=abort")
             .arg("-Crelocation-model=pic")
@@ -91,19 +91,104 @@ _start:
     shim_main = sym shim_main
 );
 
+#[cfg(target_arch = "aarch64")]
+global_asm!(
+    r#"
+    .pushsection .text.entry
+
+    .global _start
+_start:
+    mov x29, sp // Save initial stack pointer
+
+    // Args for shim_main
+    mov x0, sp
+    adr x1, shim_data
+    adr x2, shim_base
+
+    // Stack is already aligned to 16 bytes on entry
+    bl {shim_main}
+
+    mov sp, x29 // Restore stack
+    br x0 // Jump to interp_entry
+
+    .popsection
+    "#,
+    shim_main = sym shim_main
+);
+
+#[cfg(target_arch = "riscv64")]
+global_asm!(
+    r#"
+    .pushsection .text.entry
+
+    .global _start
+_start:
+    mv s0, sp // Save initial stack pointer
+
+    // Args for shim_main
+    mv a0, sp
+    lla a1, shim_data
+    lla a2, shim_base
+
+    andi sp, sp, -16 // Align stack for function call
+    call {shim_main}
+
+    mv sp, s0 // Restore stack
+    jr a0 // Jump to interp_entry
+
+    .popsection
+    "#,
+    shim_main = sym shim_main
+);
+
+// LLVM cannot parse differences of symbols in Intel syntax memory operands, so this one is in AT&T syntax
+#[cfg(target_arch = "x86")]
+global_asm!(
+    r#"
+    .pushsection .text.entry
+
+    .global _start
+_start:
+    movl %esp, %ebp // Save initial stack pointer
+
+    // There is no pc-relative addressing on i386, so find out where we are first
+    call .Lget_pc
+.Lget_pc:
+    popl %ecx
+    leal (shim_base - .Lget_pc)(%ecx), %eax
+    leal (shim_data - .Lget_pc)(%ecx), %ecx
+
+    andl $-16, %esp // Align stack for function call
+    subl $4, %esp
+
+    // Args for shim_main, pushed right to left
+    pushl %eax
+    pushl %ecx
+    pushl %ebp
+
+    call {shim_main}
+
+    movl %ebp, %esp // Restore stack
+    jmp *%eax // Jump to interp_entry
+
+    .popsection
+    "#,
+    shim_main = sym shim_main,
+    options(att_syntax)
+);
+
 // >>> Here be dragons <<<
 
 mod intrinsic {
     use super::*;
 
-    extern "rust-intrinsic" {
-        #[rustc_safe_intrinsic]
-        #[rustc_nounwind]
-        pub(super) fn wrapping_add<T: Copy>(a: T, b: T) -> T;
+    #[rustc_intrinsic]
+    #[rustc_nounwind]
+    pub(super) fn wrapping_add<T: Copy>(a: T, b: T) -> T;
 
-        #[rustc_nounwind]
-        pub(super) fn offset<Ptr, Delta>(dst: Ptr, offset: Delta) -> Ptr;
-    }
+    #[rustc_intrinsic]
+    #[rustc_nounwind]
+    pub(super) unsafe fn offset<Ptr, Delta>(dst: Ptr, offset: Delta) -> Ptr;
 }
 
 unsafe fn offset<T>(dst: *mut T, offset: usize) -> *mut T {
@@ -117,10 +202,16 @@ fn wrapping_add(a: usize, b: usize) -> usize {
 #[rustc_builtin_macro]
 pub macro global_asm() {}
 
+#[lang = "pointee_sized"]
+trait PointeeSized {}
+
+#[lang = "meta_sized"]
+trait MetaSized: PointeeSized {}
+
 #[lang = "sized"]
-trait Sized {}
+trait Sized: MetaSized {}
 
-#[lang = "receiver"]
+#[lang = "legacy_receiver"]
 trait Receiver {}
 impl<T: ?Sized> Receiver for &T {}
 
@@ -133,6 +224,10 @@ impl<T> Copy for *mut T {}
 impl Copy for usize {}
 impl Copy for bool {}
 
+#[lang = "structural_peq"]
+trait StructuralPartialEq {}
+impl StructuralPartialEq for usize {}
+
 #[allow(dead_code)] // Spurious
 #[lang = "eq"]
 trait PartialEq<Rhs: ?Sized = Self> {
@@ -1,14 +1,26 @@
-use object::{elf::*, Endianness};
-use object::write::elf::{Class, FileHeader, ProgramHeader, Rel, SectionHeader, Sym, Writer};
+use std::collections::HashMap;
+
+use log::{debug, warn};
+use object::{elf::*, Endian, Endianness};
+use object::write::elf::{Class, FileHeader, ProgramHeader, Rel, SectionHeader, Sym, Verdef, Verneed, Vernaux, Writer};
 
 use crate::repr::*;
+use crate::error::Error;
 
 fn make_static_str(s: impl AsRef<str>) -> &'static str {
     s.as_ref().to_owned().leak()
 }
 
+// How the words of the data part of the shim are encoded.
+#[derive(Clone, Copy)]
+struct ShimFormat {
+    endian: Endianness,
+    class: Class,
+}
+
 fn make_shim(
     machine: u16,
+    format: ShimFormat,
     base: u64,
     interp_base: u64,
     interp_phdrs: usize,
@@ -39,10 +51,16 @@ fn make_shim(
         };
     }
 
-    let code = if machine == EM_X86_64 {
-        shim_blob!("x86_64-unknown-none").to_vec()
+    let code: &[u8] = if machine == EM_X86_64 {
+        shim_blob!("x86_64-unknown-none")
+    } else if machine == EM_AARCH64 {
+        shim_blob!("aarch64-unknown-none")
+    } else if machine == EM_RISCV {
+        shim_blob!("riscv64gc-unknown-none-elf")
+    } else if machine == EM_386 {
+        shim_blob!("i686-unknown-none")
     } else {
-        panic!("Shim not implemented for machine: {:?}", machine)
+        unreachable!("emit_elf rejects machines without a shim")
     };
 
     let mut code = code.to_vec();
@@ -50,17 +68,109 @@ fn make_shim(
     // Append data part of shim
 
     // Keep in sync with shim/main.rs
-    // TODO: Handle 32-bit stuff
-    code.extend(user_entry.wrapping_sub(base).to_le_bytes());
-    code.extend(interp_entry.wrapping_sub(base).to_le_bytes());
-    code.extend(interp_base.wrapping_sub(base).to_le_bytes());
-    code.extend((interp_phdrs as u64).to_le_bytes());
+    let mut push_word = |word: u64| {
+        if format.class.is_64 {
+            code.extend(format.endian.write_u64_bytes(word))
+        } else {
+            code.extend(format.endian.write_u32_bytes(word as u32))
+        }
+    };
+    push_word(user_entry.wrapping_sub(base));
+    push_word(interp_entry.wrapping_sub(base));
+    push_word(interp_base.wrapping_sub(base));
+    push_word(interp_phdrs as u64);
 
     code.resize(((code.len() - 1) | 0xff) + 1, 0); // pad to make it easier to edit in binja
     code
 }
 
-pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SDATA2: u8 = 0x0a;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_SDATA8: u8 = 0x0c;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+const DW_EH_PE_OMIT: u8 = 0xff;
+
+// Returns the size of a value in one of the fixed size formats of the pointer encoding (`DW_EH_PE_*`).
+fn eh_value_size(encoding: u8) -> Option<usize> {
+    match encoding & 0x0f {
+        DW_EH_PE_UDATA2 | DW_EH_PE_SDATA2 => Some(2),
+        DW_EH_PE_UDATA4 | DW_EH_PE_SDATA4 => Some(4),
+        DW_EH_PE_UDATA8 | DW_EH_PE_SDATA8 => Some(8),
+        _ => None
+    }
+}
+
+// Reads a value from `.eh_frame_hdr` in one of the fixed size formats of the pointer encoding.
+fn read_eh_value(endian: Endianness, data: &[u8], offset: &mut usize, encoding: u8) -> Option<i64> {
+    let size = eh_value_size(encoding)?;
+    let bytes = data.get(*offset..*offset + size)?;
+    *offset += size;
+    Some(match encoding & 0x0f {
+        DW_EH_PE_UDATA2 => endian.read_u16_bytes(bytes.try_into().unwrap()) as i64,
+        DW_EH_PE_SDATA2 => endian.read_u16_bytes(bytes.try_into().unwrap()) as i16 as i64,
+        DW_EH_PE_UDATA4 => endian.read_u32_bytes(bytes.try_into().unwrap()) as i64,
+        DW_EH_PE_SDATA4 => endian.read_u32_bytes(bytes.try_into().unwrap()) as i32 as i64,
+        _ => endian.read_u64_bytes(bytes.try_into().unwrap()) as i64,
+    })
+}
+
+// Relocation types used to lower the relocations in the intermediate representation for a specific machine.
+struct RelocationTypes {
+    none: u32,      // no-op
+    absolute: u32,  // = S + A
+    relative: u32,  // = B + A
+    copy: u32,      // copies symbol data at load time
+    indirect: u32,  // = result of calling B + A
+    tls_module: u32, // = TLS module index
+    tls_offset: u32, // = S + A, within the TLS block
+    thread_pointer_offset: u32, // = S + A - TP
+}
+
+const BUILD_ID_SIZE: usize = 20; // SHA-1
+
+/// The owner of the notes that are specific to Superlinker.
+pub const ELF_NOTE_SUPERLINKER: &[u8] = b"Superlinker";
+/// Note type for the build IDs of the merged images. The descriptor is a sequence of entries, each of which is
+/// the size of the image name, the size of the build ID (both 4-byte words), the image name, and the build ID,
+/// padded to 4 bytes.
+pub const NT_SUPERLINKER_SOURCE_BUILD_IDS: u32 = 1;
+
+/// Which hash tables are emitted for looking up dynamic symbols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashStyle {
+    /// Only `DT_HASH`.
+    Sysv,
+    /// Only `DT_GNU_HASH`.
+    Gnu,
+    /// Both `DT_HASH` and `DT_GNU_HASH`.
+    Both,
+}
+
+/// Options that control how an [`Image`] is lowered.
+#[derive(Debug, Clone)]
+pub struct EmitOptions {
+    /// Which hash tables are emitted; both by default.
+    pub hash_style: HashStyle,
+    /// Whether the stack is made non-executable even if some of the merged images require an executable stack.
+    pub force_non_executable_stack: bool,
+    /// Whether RELRO ranges that cannot be coalesced into one are left writable, except for the largest one, instead
+    /// of failing.
+    pub allow_partial_relro: bool,
+}
+
+impl Default for EmitOptions {
+    fn default() -> Self {
+        EmitOptions { hash_style: HashStyle::Both, force_non_executable_stack: false, allow_partial_relro: false }
+    }
+}
+
+/// Lowers an [`Image`] into an ELF executable or shared library.
+pub fn emit_elf(image: &Image, options: &EmitOptions) -> Result<Vec<u8>, Error> {
     #[derive(Debug)]
     enum InterpreterOut {
         Path { bytes: Vec<u8> },
@@ -84,17 +194,227 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
         index: object::write::elf::SymbolIndex,
         name: object::write::StringId,
         hash: u32,
+        gnu_hash: u32,
+    }
+
+    #[derive(Debug)]
+    struct VersionNeedOut<'a> {
+        file: &'a str,
+        file_name: object::write::StringId,
+        versions: Vec<(&'a str, object::write::StringId)>,
     }
 
-    let (endian, class, is_rela);
+    let emit_error = |error| Error::Emit { image: image.display_image_name().to_owned(), error };
+    let malformed = |reason: &str| Error::Malformed {
+        image: image.display_image_name().to_owned(),
+        reason: reason.to_owned()
+    };
+
+    let endian = match image.byte_order {
+        ByteOrder::Little => Endianness::Little,
+        ByteOrder::Big => Endianness::Big,
+    };
+    let (class, is_rela, reloc_types);
     if image.machine == object::elf::EM_X86_64 {
-        endian  = Endianness::Little;
         class   = Class { is_64: true };
         is_rela = true;
+        reloc_types = RelocationTypes {
+            none: R_X86_64_NONE,
+            absolute: R_X86_64_64,
+            relative: R_X86_64_RELATIVE,
+            copy: R_X86_64_COPY,
+            indirect: R_X86_64_IRELATIVE,
+            tls_module: R_X86_64_DTPMOD64,
+            tls_offset: R_X86_64_DTPOFF64,
+            thread_pointer_offset: R_X86_64_TPOFF64,
+        };
+    } else if image.machine == object::elf::EM_AARCH64 {
+        class   = Class { is_64: true };
+        is_rela = true;
+        reloc_types = RelocationTypes {
+            none: R_AARCH64_NONE,
+            absolute: R_AARCH64_ABS64,
+            relative: R_AARCH64_RELATIVE,
+            copy: R_AARCH64_COPY,
+            indirect: R_AARCH64_IRELATIVE,
+            tls_module: R_AARCH64_TLS_DTPMOD,
+            tls_offset: R_AARCH64_TLS_DTPREL,
+            thread_pointer_offset: R_AARCH64_TLS_TPREL,
+        };
+    } else if image.machine == object::elf::EM_RISCV {
+        class   = Class { is_64: true };
+        is_rela = true;
+        reloc_types = RelocationTypes {
+            none: R_RISCV_NONE,
+            absolute: R_RISCV_64,
+            relative: R_RISCV_RELATIVE,
+            copy: R_RISCV_COPY,
+            indirect: R_RISCV_IRELATIVE,
+            tls_module: R_RISCV_TLS_DTPMOD64,
+            tls_offset: R_RISCV_TLS_DTPREL64,
+            thread_pointer_offset: R_RISCV_TLS_TPREL64,
+        };
+    } else if image.machine == object::elf::EM_386 {
+        class   = Class { is_64: false };
+        is_rela = false;
+        reloc_types = RelocationTypes {
+            none: R_386_NONE,
+            absolute: R_386_32,
+            relative: R_386_RELATIVE,
+            copy: R_386_COPY,
+            indirect: R_386_IRELATIVE,
+            tls_module: R_386_TLS_DTPMOD32,
+            tls_offset: R_386_TLS_DTPOFF32,
+            thread_pointer_offset: R_386_TLS_TPOFF,
+        };
     } else {
-        panic!("Unhandled machine: {}", image.machine)
+        return Err(Error::UnsupportedMachine { image: image.display_image_name().to_owned(), machine: image.machine })
+    }
+    if class.is_64 != (image.word_size == WordSize::Bits64) {
+        return Err(malformed("word size does not match machine"))
+    }
+    let word_size = image.word_size.bytes() as usize;
+    let word_bytes = |word: u64| {
+        if class.is_64 {
+            endian.write_u64_bytes(word).to_vec()
+        } else {
+            endian.write_u32_bytes(word as u32).to_vec()
+        }
+    };
+
+    // Unwinders find the unwind information (FDEs) of the function they are unwinding through PT_GNU_EH_FRAME, which
+    // points to `.eh_frame_hdr`. There can be only one of it per object, so the binary search tables of the merged
+    // images are combined into a new one, which points to the FDEs in the image segments. The pointer to `.eh_frame`
+    // is only used if there's no search table (and so cannot be meaningfully merged); it points to the first one.
+    //
+    // The pointers in `.eh_frame_hdr` are relative to its own location in practice. Absolute pointers would have
+    // been invalidated by rebasing, so they are not supported.
+    let mut out_eh_frame = None;
+    let mut out_eh_frame_table = Vec::new();
+    for eh_frame_hdr in image.eh_frame_hdrs.iter() {
+        let data = image.read_bytes(eh_frame_hdr.addr, eh_frame_hdr.size)
+            .ok_or_else(|| malformed(".eh_frame_hdr out of bounds"))?;
+        let &[version, eh_frame_ptr_enc, fde_count_enc, table_enc, ..] = &data[..] else {
+            return Err(malformed(".eh_frame_hdr is truncated"))
+        };
+        // A search table that cannot be merged is left out, which only affects unwinding through the functions of
+        // its image, so that it doesn't prevent linking images that never throw exceptions.
+        let unsupported_reason = if version != 1 {
+            Some(format!("has version {}", version))
+        } else if fde_count_enc == DW_EH_PE_OMIT || table_enc == DW_EH_PE_OMIT {
+            Some("has no search table".to_owned())
+        } else {
+            [(eh_frame_ptr_enc, true), (fde_count_enc, false), (table_enc, true)].into_iter()
+                .find(|&(encoding, is_pointer)| {
+                    let is_supported_application = if is_pointer {
+                        matches!(encoding & 0xf0, DW_EH_PE_PCREL | DW_EH_PE_DATAREL)
+                    } else {
+                        encoding & 0xf0 == 0
+                    };
+                    eh_value_size(encoding).is_none() || !is_supported_application
+                })
+                .map(|(encoding, _)| format!("uses value encoding {:#04x}", encoding))
+        };
+        if let Some(reason) = unsupported_reason {
+            warn!("emit_elf: leaving out .eh_frame_hdr of {}, which {}; exceptions cannot be unwound through it",
+                eh_frame_hdr.image_name, reason);
+            continue
+        }
+        let read_pointer = |offset: &mut usize, encoding: u8| {
+            let field_addr = eh_frame_hdr.addr + *offset as u64;
+            let value = read_eh_value(endian, &data, offset, encoding)
+                .ok_or_else(|| malformed(".eh_frame_hdr is truncated"))?;
+            Ok(if encoding & 0xf0 == DW_EH_PE_PCREL {
+                field_addr.wrapping_add(value as u64)
+            } else {
+                eh_frame_hdr.addr.wrapping_add(value as u64)
+            })
+        };
+        let mut offset = 4;
+        let eh_frame = read_pointer(&mut offset, eh_frame_ptr_enc)?;
+        out_eh_frame.get_or_insert(eh_frame);
+        let fde_count = read_eh_value(endian, &data, &mut offset, fde_count_enc)
+            .ok_or_else(|| malformed(".eh_frame_hdr is truncated"))?;
+        for _ in 0..fde_count {
+            let initial_location = read_pointer(&mut offset, table_enc)?;
+            let fde = read_pointer(&mut offset, table_enc)?;
+            out_eh_frame_table.push((initial_location, fde));
+        }
     }
+    out_eh_frame_table.sort_by_key(|&(initial_location, _fde)| initial_location);
 
+    // Most notes describe the image as a whole, so only the first note of each owner and type is kept, which is the
+    // one of the executable (or the first merged library). The exception is the ABI tag, which is the minimum kernel
+    // version required by the image; the highest one of the merged images is kept. The build IDs of the merged
+    // images identify their code, not the merged image's, so they are replaced by a new build ID and moved into
+    // a note of our own, which lets crash reports be mapped back to the inputs.
+    let abi_tag_version = |note: &Note| {
+        note.desc.chunks_exact(4).skip(1).map(|word| endian.read_u32_bytes(word.try_into().unwrap())).collect::<Vec<_>>()
+    };
+    let mut out_notes: Vec<&Note> = Vec::new();
+    let mut out_source_build_ids: Vec<&Note> = Vec::new();
+    for note in image.notes.iter() {
+        if note.name.as_bytes() == ELF_NOTE_GNU && note.note_type == NT_GNU_BUILD_ID {
+            out_source_build_ids.push(note);
+            continue
+        }
+        let out_note = out_notes.iter_mut()
+            .find(|out_note| out_note.name == note.name && out_note.note_type == note.note_type);
+        match out_note {
+            None => out_notes.push(note),
+            Some(out_note) if note.name.as_bytes() == ELF_NOTE_GNU && note.note_type == NT_GNU_ABI_TAG &&
+                    note.desc.get(..4) == out_note.desc.get(..4) => {
+                if abi_tag_version(note) > abi_tag_version(out_note) {
+                    *out_note = note
+                }
+            }
+            Some(_) => ()
+        }
+    }
+    // Notes are padded to 4 bytes, except for the GNU property note, which is padded to the word size, and so gets
+    // a PT_NOTE of its own.
+    let write_note = |bytes: &mut Vec<u8>, name: &[u8], note_type: u32, desc: &[u8], align: usize| {
+        bytes.extend_from_slice(&endian.write_u32_bytes(name.len() as u32 + 1));
+        bytes.extend_from_slice(&endian.write_u32_bytes(desc.len() as u32));
+        bytes.extend_from_slice(&endian.write_u32_bytes(note_type));
+        bytes.extend_from_slice(name);
+        bytes.push(0);
+        bytes.resize(bytes.len().next_multiple_of(align), 0);
+        bytes.extend_from_slice(desc);
+        bytes.resize(bytes.len().next_multiple_of(align), 0);
+    };
+    // The build ID is computed once the rest of the file is written, and is a hash of its contents. It is
+    // deterministic, since the output only depends on the inputs.
+    let mut out_notes_data = Vec::new();
+    write_note(&mut out_notes_data, ELF_NOTE_GNU, NT_GNU_BUILD_ID, &[0; BUILD_ID_SIZE], 4);
+    let out_build_id_offset = out_notes_data.len() - BUILD_ID_SIZE;
+    if !out_source_build_ids.is_empty() {
+        let mut desc = Vec::new();
+        for note in out_source_build_ids.iter() {
+            desc.extend_from_slice(&endian.write_u32_bytes(note.image_name.len() as u32));
+            desc.extend_from_slice(&endian.write_u32_bytes(note.desc.len() as u32));
+            desc.extend_from_slice(note.image_name.as_bytes());
+            desc.extend_from_slice(&note.desc);
+            desc.resize(desc.len().next_multiple_of(4), 0);
+        }
+        write_note(&mut out_notes_data, ELF_NOTE_SUPERLINKER, NT_SUPERLINKER_SOURCE_BUILD_IDS, &desc, 4);
+    }
+    for note in out_notes.iter() {
+        write_note(&mut out_notes_data, note.name.as_bytes(), note.note_type, &note.desc, 4);
+    }
+    let mut out_gnu_property_note_data = Vec::new();
+    if !image.gnu_properties.is_empty() {
+        let mut desc = Vec::new();
+        for property in image.gnu_properties.iter() {
+            desc.extend_from_slice(&endian.write_u32_bytes(property.property_type));
+            desc.extend_from_slice(&endian.write_u32_bytes(property.data.len() as u32));
+            desc.extend_from_slice(&property.data);
+            desc.resize(desc.len().next_multiple_of(word_size), 0);
+        }
+        write_note(&mut out_gnu_property_note_data, ELF_NOTE_GNU, NT_GNU_PROPERTY_TYPE_0, &desc, word_size);
+    }
+
+    let shim_format = ShimFormat { endian, class };
     let out_interp = match image.interpreter {
         Interpreter::External(ref path) => {
             let mut bytes = path.as_bytes().to_vec();
@@ -102,7 +422,7 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
             InterpreterOut::Path { bytes }
         }
         Interpreter::Internal { base, entry, segments: phdrs } => {
-            let code = make_shim(image.machine, 0, 0, 0, 0, 0); // can't resolve references yet
+            let code = make_shim(image.machine, shim_format, 0, 0, 0, 0, 0); // can't resolve references yet
             InterpreterOut::Shim { base, entry, phdrs, code_len: code.len() }
         },
         Interpreter::Absent =>
@@ -122,14 +442,58 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
         InterpreterOut::None => 0,
     };
     let tls_phdr_count = if image.tls_image.is_some() { 1 } else { 0 };
+    let eh_frame_hdr_phdr_count = if out_eh_frame.is_some() { 1 } else { 0 };
+    let note_phdr_count = /* always has the build ID */1;
+    let gnu_property_phdr_count = if out_gnu_property_note_data.is_empty() { 0 } else { 2 };
+    // Overlapping and adjacent RELRO ranges are coalesced. Dynamic loaders (both glibc and musl) only honor one
+    // PT_GNU_RELRO header, so if there are several coalesced ranges, only the largest one can be emitted, and the
+    // rest would be left writable. The ranges of different images are usually separated by writable data, and
+    // cannot be made adjacent without moving that data, so losing RELRO for them has to be asked for.
+    let mut relro_ranges = image.relro_ranges.iter().collect::<Vec<_>>();
+    relro_ranges.sort_by_key(|relro_range| relro_range.addr);
+    let mut coalesced_relro_ranges: Vec<(AddressRange, Vec<&str>)> = Vec::new();
+    for relro_range in relro_ranges {
+        match coalesced_relro_ranges.last_mut() {
+            Some((last_range, image_names)) if relro_range.addr <= last_range.addr + last_range.size => {
+                let end = (last_range.addr + last_range.size).max(relro_range.addr + relro_range.size);
+                last_range.size = end - last_range.addr;
+                image_names.push(&relro_range.image_name);
+            }
+            _ => coalesced_relro_ranges.push((
+                AddressRange { addr: relro_range.addr, size: relro_range.size },
+                vec![&relro_range.image_name]
+            ))
+        }
+    }
+    coalesced_relro_ranges.sort_by_key(|(relro_range, _)| std::cmp::Reverse(relro_range.size));
+    let out_relro_range = coalesced_relro_ranges.first().map(|(relro_range, _)| *relro_range);
+    if coalesced_relro_ranges.len() > 1 && !options.allow_partial_relro {
+        let image_names = coalesced_relro_ranges.iter().skip(1)
+            .flat_map(|(_, image_names)| image_names.iter().copied())
+            .collect::<Vec<_>>();
+        return Err(Error::UnsupportedFeature {
+            image: image.display_image_name().to_owned(),
+            feature: format!("RELRO ranges that cannot be coalesced with the largest one (of {})",
+                image_names.join(", "))
+        })
+    }
+    for (relro_range, image_names) in coalesced_relro_ranges.iter().skip(1) {
+        warn!("emit_elf: cannot make {:#x}..{:#x} of {} read-only after relocation, since only one RELRO range \
+            is supported", relro_range.addr, relro_range.addr + relro_range.size, image_names.join(", "));
+    }
     let phdr_count =
         /* PT_PHDR */1
         + /* PT_LOAD for ELF file and program headers */1
         + /* PT_INTERP or PT_LOAD for interpreter thunk */interp_phdr_count
         + /* PT_DYNAMIC */1
         + /* PT_TLS if needed */tls_phdr_count
+        + /* PT_GNU_EH_FRAME if needed */eh_frame_hdr_phdr_count
+        + /* PT_NOTE if needed */note_phdr_count
+        + /* PT_NOTE and PT_GNU_PROPERTY if needed */gnu_property_phdr_count
         + /* PT_LOAD for PT_DYNAMIC, PT_TLS, etc */1
-        + /* PT_LOAD[..] */image.segments.len();
+        + /* PT_LOAD[..] */image.segments.len()
+        + /* PT_GNU_RELRO if needed */if out_relro_range.is_some() { 1 } else { 0 }
+        + /* PT_GNU_STACK */1;
     obj_writer.reserve_program_headers(phdr_count as u32);
     let obj_interp_offset = if let InterpreterOut::Path { bytes } = &out_interp {
         obj_writer.reserve(bytes.len(), 1)
@@ -149,12 +513,82 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
     for dependency in image.dependencies.iter() {
         out_needful.push(obj_writer.add_dynamic_string(dependency.as_ref()));
     }
+    // DT_GNU_HASH requires the dynamic symbols that are looked up (the defined ones) to come last, sorted by their
+    // hash bucket; the rest of the symbols are not hashed at all. ELF also requires local symbols to come first.
+    // The sort is stable, so the order of the symbols is otherwise the same as in the image.
+    let emit_sysv_hash = matches!(options.hash_style, HashStyle::Sysv | HashStyle::Both);
+    let emit_gnu_hash = matches!(options.hash_style, HashStyle::Gnu | HashStyle::Both);
+    let is_defined = |symbol: &Symbol|
+        symbol.abs || symbol.value != 0 || (symbol.kind == SymbolKind::Tls && symbol.scope != SymbolScope::Import);
+    let is_hashed = |symbol: &Symbol| symbol.scope != SymbolScope::Local && is_defined(symbol);
+    let gnu_hash_symbol_count = image.symbols.iter().filter(|symbol| is_hashed(symbol)).count() as u32;
+    let gnu_hash_symbol_base = 1 + image.symbols.len() as u32 - gnu_hash_symbol_count; // null symbol first
+    let gnu_hash_bucket_count = (gnu_hash_symbol_count / 4).max(1);
+    let gnu_hash_bloom_count = (gnu_hash_symbol_count * 12 / (word_size as u32 * 8)).next_power_of_two();
+    let gnu_hash_bloom_shift = 26;
+    let mut symbols = image.symbols.iter().collect::<Vec<_>>();
+    symbols.sort_by_key(|symbol| {
+        if symbol.scope == SymbolScope::Local {
+            (0, 0)
+        } else if !is_hashed(symbol) {
+            (1, 0)
+        } else {
+            (2, object::elf::gnu_hash(symbol.name.as_ref()) % gnu_hash_bucket_count)
+        }
+    });
     let mut out_dynsyms = Vec::new();
-    for symbol in image.symbols.iter() {
+    for symbol in symbols.iter() {
         let index = obj_writer.reserve_dynamic_symbol_index();
         let name = obj_writer.add_dynamic_string(symbol.name.as_ref());
         let hash = object::elf::hash(symbol.name.as_ref());
-        out_dynsyms.push(DynamicSymbolOut { index, name, hash });
+        let gnu_hash = object::elf::gnu_hash(symbol.name.as_ref());
+        out_dynsyms.push(DynamicSymbolOut { index, name, hash, gnu_hash });
+    }
+    // Versions defined by the image are numbered first, starting after the base version (the image itself), and
+    // versions required from other images are numbered after that. Only the versions that are actually used by
+    // the symbols are emitted, and if none are, the version tables are omitted entirely.
+    let mut out_verdefs = Vec::new();
+    let mut out_verneeds: Vec<VersionNeedOut> = Vec::new();
+    for symbol in symbols.iter() {
+        match symbol.version {
+            Some(SymbolVersion { ref name, file: None, .. })
+                    if !out_verdefs.iter().any(|&(verdef_name, _)| verdef_name == name) => {
+                out_verdefs.push((&name[..], obj_writer.add_dynamic_string(name.as_ref())));
+            }
+            Some(SymbolVersion { ref name, file: Some(ref file), .. }) => {
+                let verneed_index = match out_verneeds.iter().position(|verneed| verneed.file == file) {
+                    Some(verneed_index) => verneed_index,
+                    None => {
+                        let file_name = obj_writer.add_dynamic_string(file.as_ref());
+                        out_verneeds.push(VersionNeedOut { file, file_name, versions: Vec::new() });
+                        out_verneeds.len() - 1
+                    }
+                };
+                let versions = &mut out_verneeds[verneed_index].versions;
+                if !versions.iter().any(|&(version_name, _)| version_name == name) {
+                    versions.push((name, obj_writer.add_dynamic_string(name.as_ref())));
+                }
+            }
+            _ => ()
+        }
+    }
+    let has_versions = !(out_verdefs.is_empty() && out_verneeds.is_empty());
+    if !out_verdefs.is_empty() {
+        let base_name = image.display_image_name();
+        out_verdefs.insert(0, (base_name, obj_writer.add_dynamic_string(base_name.as_ref())));
+    }
+    let mut out_version_indices = HashMap::new();
+    for (verdef_index, (name, _)) in out_verdefs.iter().enumerate() {
+        out_version_indices.insert((None, *name), verdef_index as u16 + 1);
+    }
+    // Version indices 0 and 1 are local and global, but index 1 is also the base version if there are any verdefs,
+    // so verneed indices follow the verdef indices, and start at 2 at the earliest.
+    let mut next_version_index = (out_verdefs.len() as u16 + 1).max(2);
+    for verneed in out_verneeds.iter() {
+        for (name, _) in verneed.versions.iter() {
+            out_version_indices.insert((Some(verneed.file), *name), next_version_index);
+            next_version_index += 1;
+        }
     }
     obj_writer.reserve(0, image.alignment as usize);
     let dynamic_count =
@@ -164,7 +598,8 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
         + /* DT_STRSZ */1
         + /* DT_SYMENT */1
         + /* DT_SYMTAB */1
-        + /* DT_HASH */1
+        + /* DT_HASH if needed */emit_sysv_hash as usize
+        + /* DT_GNU_HASH if needed */emit_gnu_hash as usize
         + /* DT_REL(A) */1
         + /* DT_REL(A)SZ */1
         + /* DT_REL(A)ENT */1
@@ -172,24 +607,43 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
         + /* DT_INIT_ARRAYSZ */1
         + /* DT_FINI_ARRAY */1
         + /* DT_FINI_ARRAYSZ */1
+        + /* DT_VERSYM if needed */has_versions as usize
+        + /* DT_VERDEF, DT_VERDEFNUM if needed */if out_verdefs.is_empty() { 0 } else { 2 }
+        + /* DT_VERNEED, DT_VERNEEDNUM if needed */if out_verneeds.is_empty() { 0 } else { 2 }
         + /* DT_NULL */1;
     let obj_dynamic_offset = obj_writer.reserve_dynamic(dynamic_count);
     let obj_dynstr_offset = obj_writer.reserve_dynstr();
     let obj_dynstr_length = obj_writer.dynstr_len();
     let obj_dynsym_offset = obj_writer.reserve_dynsym();
-    let hash_bucket_count = 4; // TODO: chosen at random
+    let hash_bucket_count = (out_dynsyms.len() as u32).max(1);
     let hash_index_base = 1; // null symbol
     let hash_chain_count = hash_index_base + out_dynsyms.len() as u32;
-    let obj_hash_offset = obj_writer.reserve_hash(hash_bucket_count, hash_chain_count);
+    let obj_hash_offset = if emit_sysv_hash {
+        obj_writer.reserve_hash(hash_bucket_count, hash_chain_count)
+    } else { 0 };
+    let obj_gnu_hash_offset = if emit_gnu_hash {
+        obj_writer.reserve_gnu_hash(gnu_hash_bloom_count, gnu_hash_bucket_count, gnu_hash_symbol_count)
+    } else { 0 };
+    let obj_versym_offset = if has_versions { obj_writer.reserve_gnu_versym() } else { 0 };
+    let obj_verdef_offset = obj_writer.reserve_gnu_verdef(out_verdefs.len(), out_verdefs.len());
+    let obj_verneed_offset = obj_writer.reserve_gnu_verneed(out_verneeds.len(),
+        out_verneeds.iter().map(|verneed| verneed.versions.len()).sum());
     let relocation_count = image.relocations.len() + image.initializers.len() + image.finalizers.len();
     let obj_reloc_offset = obj_writer.reserve_relocations(relocation_count, is_rela);
-    let obj_dt_init_array_length = image.initializers.len() * if class.is_64 { 8 } else { 4 };
-    let obj_dt_init_array_offset = obj_writer.reserve(obj_dt_init_array_length, class.align() as usize);
-    let obj_dt_fini_array_length = image.finalizers.len() * if class.is_64 { 8 } else { 4 };
-    let obj_dt_fini_array_offset = obj_writer.reserve(obj_dt_fini_array_length, class.align() as usize);
-    let obj_tls_offset = if let Some(ref tls_data) = image.tls_image {
-        obj_writer.reserve(tls_data.len(), class.align() as usize)
+    let obj_dt_init_array_length = image.initializers.len() * word_size;
+    let obj_dt_init_array_offset = obj_writer.reserve(obj_dt_init_array_length, class.align());
+    let obj_dt_fini_array_length = image.finalizers.len() * word_size;
+    let obj_dt_fini_array_offset = obj_writer.reserve(obj_dt_fini_array_length, class.align());
+    let obj_tls_offset = if let Some(ref tls_image) = image.tls_image {
+        obj_writer.reserve(tls_image.data.len(), tls_image.alignment as usize)
     } else { 0 };
+    let obj_eh_frame_hdr_length = /* header */4 + /* eh_frame_ptr */4 + /* fde_count */4
+        + /* table */out_eh_frame_table.len() * 8;
+    let obj_eh_frame_hdr_offset = if out_eh_frame.is_some() {
+        obj_writer.reserve(obj_eh_frame_hdr_length, 4)
+    } else { 0 };
+    let obj_notes_offset = obj_writer.reserve(out_notes_data.len(), 4);
+    let obj_gnu_property_note_offset = obj_writer.reserve(out_gnu_property_note_data.len(), word_size);
     let obj_dynamic_end = obj_writer.reserved_len();
 
     // Reserve space for section headers.
@@ -200,12 +654,37 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
     obj_writer.reserve_dynamic_section_index();
     obj_writer.reserve_dynstr_section_index();
     let obj_dynsym_section_index = obj_writer.reserve_dynsym_section_index();
-    obj_writer.reserve_hash_section_index();
+    if emit_sysv_hash {
+        obj_writer.reserve_hash_section_index();
+    }
+    if emit_gnu_hash {
+        obj_writer.reserve_gnu_hash_section_index();
+    }
+    if has_versions {
+        obj_writer.reserve_gnu_versym_section_index();
+    }
+    if !out_verdefs.is_empty() {
+        obj_writer.reserve_gnu_verdef_section_index();
+    }
+    if !out_verneeds.is_empty() {
+        obj_writer.reserve_gnu_verneed_section_index();
+    }
     let _obj_reloc_dyn_section_index = obj_writer.reserve_section_index();
     let obj_reloc_dyn_section_name = obj_writer.add_section_name(if is_rela { b".rela.dyn" } else { b".rel.dyn" });
     let obj_shim_section_index_name = if let InterpreterOut::Shim { .. } = out_interp {
         Some((obj_writer.reserve_section_index(), obj_writer.add_section_name(b"shim")))
     } else { None };
+    let obj_tls_section_index_name = if image.tls_image.is_some() {
+        Some((obj_writer.reserve_section_index(), obj_writer.add_section_name(b"tls")))
+    } else { None };
+    let obj_eh_frame_hdr_section_index_name = if out_eh_frame.is_some() {
+        Some((obj_writer.reserve_section_index(), obj_writer.add_section_name(b".eh_frame_hdr")))
+    } else { None };
+    let obj_notes_section_index_name =
+        Some((obj_writer.reserve_section_index(), obj_writer.add_section_name(b".note")));
+    let obj_gnu_property_note_section_index_name = if !out_gnu_property_note_data.is_empty() {
+        Some((obj_writer.reserve_section_index(), obj_writer.add_section_name(b".note.gnu.property")))
+    } else { None };
     let mut out_load_sections = Vec::new();
     for (segment_index, segment) in image.segments.iter().enumerate() {
         let mut make_section = |name, size, addr, load| {
@@ -224,7 +703,7 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
         let dataless_name = format!("image.{}.rwz", segment_index);
         if segment.data.len() as u64 == segment.size {
             make_section(dataful_name, segment.data.len() as u64, segment.addr, /*load=*/true);
-        } else if segment.data.len() == 0 {
+        } else if segment.data.is_empty() {
             make_section(dataless_name, segment.size, segment.addr, /*load=*/false);
         } else {
             make_section(dataful_name, segment.data.len() as u64, segment.addr, /*load=*/true);
@@ -237,9 +716,11 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
 
     // Reserve space for image segments.
     let image_file_offset = obj_writer.reserve(0, image.alignment as usize);
-    eprintln!("emit_elf: emitting images at offset {:+#x}", image_file_offset);
+    debug!("emit_elf: emitting images at offset {:+#x}", image_file_offset);
     for segment in image.segments.iter() {
-        assert!(segment.data.len() as u64 <= segment.size);
+        if segment.data.len() as u64 > segment.size {
+            return Err(malformed("segment data exceeds segment size"))
+        }
         obj_writer.reserve_until(image_file_offset + segment.addr as usize + segment.size as usize);
     }
 
@@ -256,7 +737,7 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
         e_machine: image.machine,
         e_entry: entry,
         e_flags: 0,
-    })?;
+    }).map_err(emit_error)?;
     // We use a 1:1 mapping between file offsets and virtual addresses (before rebasing). This is already how many
     // shared objects are laid out. It also simplifies both internal bookkeeping and debugging.
     let mut write_program_header = |type_, flags, offset, size, align| {
@@ -298,11 +779,26 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
     // that are referenced by the table. These are mapped read-write since the interpreter modifies them in-place.
     write_program_header(PT_DYNAMIC, PF_R | PF_W,
         obj_dynamic_offset, class.dyn_size() * dynamic_count, class.align() as u64);
-    if let Some(ref tls_data) = image.tls_image {
+    if let Some(ref tls_image) = image.tls_image {
         // The TLS section piggybacks on the PT_DYNAMIC's PT_LOAD. This isn't how it's usually done but it should be
         // fine, for now at least.
         write_program_header(PT_TLS, PF_R,
-            obj_tls_offset, tls_data.len(), class.align() as u64);
+            obj_tls_offset, tls_image.data.len(), tls_image.alignment);
+    }
+    if out_eh_frame.is_some() {
+        // The merged `.eh_frame_hdr` piggybacks on the PT_DYNAMIC's PT_LOAD as well.
+        write_program_header(PT_GNU_EH_FRAME, PF_R,
+            obj_eh_frame_hdr_offset, obj_eh_frame_hdr_length, /*align=*/4);
+    }
+    // And so do the notes.
+    write_program_header(PT_NOTE, PF_R,
+        obj_notes_offset, out_notes_data.len(), /*align=*/4);
+    if !out_gnu_property_note_data.is_empty() {
+        // The dynamic loader finds the GNU properties (e.g. whether CET can be enabled) through PT_GNU_PROPERTY.
+        write_program_header(PT_NOTE, PF_R,
+            obj_gnu_property_note_offset, out_gnu_property_note_data.len(), word_size as u64);
+        write_program_header(PT_GNU_PROPERTY, PF_R,
+            obj_gnu_property_note_offset, out_gnu_property_note_data.len(), word_size as u64);
     }
     write_program_header(PT_LOAD, PF_R | PF_W,
         obj_dynamic_offset, obj_dynamic_end - obj_dynamic_offset, class.align() as u64);
@@ -318,16 +814,49 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
         write_program_header(PT_LOAD, obj_flags,
             image_file_offset + segment.addr as usize, segment.size as usize, image.alignment);
     }
+    // The stack is executable if any of the merged images requires it to be, since there is only one stack.
+    // This is a common cause of unexpectedly executable stacks, so the culprits are named.
+    let mut out_stack_executable = false;
+    for stack_requirement in image.stack_requirements.iter().filter(|stack_requirement| stack_requirement.executable) {
+        if options.force_non_executable_stack {
+            warn!("emit_elf: ignoring executable stack requirement of image {:?}", stack_requirement.image_name);
+        } else {
+            warn!("emit_elf: image {:?} requires an executable stack", stack_requirement.image_name);
+            out_stack_executable = true;
+        }
+    }
+    let out_stack_size = image.stack_requirements.iter()
+        .map(|stack_requirement| stack_requirement.size)
+        .max()
+        .unwrap_or(0);
+    // The RELRO ranges are within the image segments, and are made read-only by the interpreter once it's done
+    // relocating them.
+    if let Some(relro_range) = out_relro_range {
+        write_program_header(PT_GNU_RELRO, PF_R,
+            image_file_offset + relro_range.addr as usize, relro_range.size as usize, /*align=*/1);
+    }
+    // PT_GNU_STACK only has flags and (optionally) the size of the stack.
+    obj_writer.write_program_header(&ProgramHeader {
+        p_type: PT_GNU_STACK,
+        p_flags: if out_stack_executable { PF_R | PF_W | PF_X } else { PF_R | PF_W },
+        p_offset: 0,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: 0,
+        p_memsz: out_stack_size,
+        p_align: 16,
+    });
 
     // Write dynamic linker information.
     match &out_interp {
         InterpreterOut::Path { bytes } => {
             obj_writer.pad_until(obj_interp_offset);
-            obj_writer.write(&bytes);
+            obj_writer.write(bytes);
         }
         InterpreterOut::Shim { base: interp_base, entry: interp_entry, phdrs: interp_phdrs, code_len } => {
             let code = make_shim(
                 image.machine,
+                shim_format,
                 obj_shim_offset as u64,
                 image_file_offset as u64 + *interp_base,
                 *interp_phdrs,
@@ -351,7 +880,12 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
     obj_writer.write_dynamic(DT_STRSZ, obj_dynstr_length as u64);
     obj_writer.write_dynamic(DT_SYMENT, class.sym_size() as u64);
     obj_writer.write_dynamic(DT_SYMTAB, obj_dynsym_offset as u64);
-    obj_writer.write_dynamic(DT_HASH, obj_hash_offset as u64);
+    if emit_sysv_hash {
+        obj_writer.write_dynamic(DT_HASH, obj_hash_offset as u64);
+    }
+    if emit_gnu_hash {
+        obj_writer.write_dynamic(DT_GNU_HASH, obj_gnu_hash_offset as u64);
+    }
     obj_writer.write_dynamic(if is_rela { DT_RELA } else { DT_REL },
         obj_reloc_offset as u64);
     obj_writer.write_dynamic(if is_rela { DT_RELASZ } else { DT_RELSZ },
@@ -362,13 +896,26 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
     obj_writer.write_dynamic(DT_INIT_ARRAYSZ, obj_dt_init_array_length as u64);
     obj_writer.write_dynamic(DT_FINI_ARRAY, obj_dt_fini_array_offset as u64);
     obj_writer.write_dynamic(DT_FINI_ARRAYSZ, obj_dt_fini_array_length as u64);
+    if has_versions {
+        obj_writer.write_dynamic(DT_VERSYM, obj_versym_offset as u64);
+    }
+    if !out_verdefs.is_empty() {
+        obj_writer.write_dynamic(DT_VERDEF, obj_verdef_offset as u64);
+        obj_writer.write_dynamic(DT_VERDEFNUM, out_verdefs.len() as u64);
+    }
+    if !out_verneeds.is_empty() {
+        obj_writer.write_dynamic(DT_VERNEED, obj_verneed_offset as u64);
+        obj_writer.write_dynamic(DT_VERNEEDNUM, out_verneeds.len() as u64);
+    }
     obj_writer.write_dynamic(DT_NULL, 0);
     obj_writer.write_dynstr();
     obj_writer.write_null_dynamic_symbol();
-    for symbol in image.symbols.iter() {
+    for symbol in symbols.iter() {
         let obj_symtype = match symbol.kind {
             SymbolKind::Code => STT_FUNC,
+            SymbolKind::Ifunc => STT_GNU_IFUNC,
             SymbolKind::Data => STT_OBJECT,
+            SymbolKind::Tls => STT_TLS,
             SymbolKind::Unknown => STT_NOTYPE,
         };
         let obj_bind = match symbol.scope {
@@ -377,6 +924,12 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
             SymbolScope::Import => STB_GLOBAL,
             SymbolScope::Weak => STB_WEAK,
         };
+        let obj_visibility = match symbol.visibility {
+            SymbolVisibility::Default => STV_DEFAULT,
+            SymbolVisibility::Protected => STV_PROTECTED,
+            SymbolVisibility::Hidden => STV_HIDDEN,
+            SymbolVisibility::Internal => STV_INTERNAL,
+        };
         // In symbol tables, relocations must be associated with a section, even in an executable or shared object
         // where the address of the section is unimportant. Nevertheless, find which section they belong to.
         let (obj_value, obj_section, obj_shndx);
@@ -384,6 +937,11 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
             obj_value = symbol.value;
             obj_section = None;
             obj_shndx = SHN_ABS;
+        } else if symbol.kind == SymbolKind::Tls && symbol.scope != SymbolScope::Import {
+            // TLS symbols are offsets within the TLS segment, and belong to the TLS section.
+            obj_value = symbol.value;
+            obj_section = obj_tls_section_index_name.map(|(index, _name)| index);
+            obj_shndx = 0;
         } else if symbol.value == 0 {
             obj_value = 0;
             obj_section = None;
@@ -400,50 +958,129 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
             name: Some(obj_writer.get_dynamic_string(symbol.name.as_ref())),
             section: obj_section,
             st_info: (obj_bind << 4) | obj_symtype,
-            st_other: 0,
+            st_other: obj_visibility,
             st_shndx: obj_shndx, // automatically filled in if `section` is specified
             st_value: obj_value,
             st_size: symbol.size,
         });
     }
-    obj_writer.write_hash(hash_bucket_count, hash_chain_count, |index| {
-        Some(out_dynsyms.get(index.checked_sub(hash_index_base)? as usize)?.hash)
-    });
+    if emit_sysv_hash {
+        obj_writer.write_hash(hash_bucket_count, hash_chain_count, |index| {
+            Some(out_dynsyms.get(index.checked_sub(hash_index_base)? as usize)?.hash)
+        });
+    }
+    if emit_gnu_hash {
+        obj_writer.write_gnu_hash(gnu_hash_symbol_base, gnu_hash_bloom_shift, gnu_hash_bloom_count,
+            gnu_hash_bucket_count, gnu_hash_symbol_count, |index| {
+                out_dynsyms[(gnu_hash_symbol_base - hash_index_base + index) as usize].gnu_hash
+            });
+    }
+    if has_versions {
+        obj_writer.write_null_gnu_versym();
+        for symbol in symbols.iter() {
+            let obj_versym = match (symbol.scope, &symbol.version) {
+                (SymbolScope::Local, _) => VER_NDX_LOCAL,
+                (_, None) => VER_NDX_GLOBAL,
+                (_, Some(version)) => {
+                    let index = out_version_indices[&(version.file.as_deref(), &version.name[..])];
+                    if version.hidden { index | VERSYM_HIDDEN } else { index }
+                }
+            };
+            obj_writer.write_gnu_versym(obj_versym);
+        }
+    }
+    obj_writer.write_align_gnu_verdef();
+    for (verdef_index, (_, name)) in out_verdefs.iter().enumerate() {
+        obj_writer.write_gnu_verdef(&Verdef {
+            version: VER_DEF_CURRENT,
+            flags: if verdef_index == 0 { VER_FLG_BASE } else { 0 },
+            index: verdef_index as u16 + 1,
+            aux_count: 1,
+            name: *name,
+        });
+    }
+    obj_writer.write_align_gnu_verneed();
+    for verneed in out_verneeds.iter() {
+        obj_writer.write_gnu_verneed(&Verneed {
+            version: VER_NEED_CURRENT,
+            aux_count: verneed.versions.len() as u16,
+            file: verneed.file_name,
+        });
+        for (name, version_name) in verneed.versions.iter() {
+            obj_writer.write_gnu_vernaux(&Vernaux {
+                flags: 0,
+                index: out_version_indices[&(Some(verneed.file), *name)],
+                name: *version_name,
+            });
+        }
+    }
     obj_writer.write_align_relocation();
-    let find_symbol = |name|
-        image.symbols.iter().position(|symbol| symbol.name == name).map(|index| index + 1).unwrap_or(0) as u32;
-    for relocation in image.relocations.iter() {
+    // Relocations refer to symbols by their versioned names, since the same name can be imported with several versions.
+    let obj_symbol_indices = symbols.iter().enumerate()
+        .map(|(index, symbol)| (symbol.versioned_name(), index as u32 + 1))
+        .collect::<HashMap<_, _>>();
+    let find_symbol = |name: String| obj_symbol_indices.get(&name).copied().unwrap_or(0);
+    // With "Rel" relocations, the addend is stored in the relocated location, and the image data must be patched.
+    let mut implicit_addends = Vec::new();
+    // Resolvers of indirect functions may call other functions, so IRELATIVE relocations come after the rest.
+    let is_irelative = |relocation: &&Relocation| matches!(relocation.target, RelocationTarget::IRelative { .. });
+    let relocations = image.relocations.iter().filter(|relocation| !is_irelative(relocation))
+        .chain(image.relocations.iter().filter(is_irelative));
+    for relocation in relocations {
         let (obj_reltype, obj_relsym, obj_addend);
-        if image.machine == object::elf::EM_X86_64 {
-            match relocation.target.clone() {
-                RelocationTarget::Symbol { symbol: symbol_name, addend } => {
-                    obj_reltype = R_X86_64_64;
-                    obj_relsym = find_symbol(symbol_name);
-                    obj_addend = addend;
-                },
-                RelocationTarget::Base { addend } => {
-                    obj_reltype = R_X86_64_RELATIVE;
-                    obj_relsym = 0;
-                    obj_addend = image_file_offset as i64 + addend;
-                },
-                RelocationTarget::Copy { symbol: symbol_name } => {
-                    obj_reltype = R_X86_64_COPY;
-                    obj_relsym = find_symbol(symbol_name);
-                    obj_addend = 0;
-                },
-                RelocationTarget::None => {
-                    obj_reltype = R_X86_64_NONE;
-                    obj_relsym = 0;
-                    obj_addend = 0;
-                }
-                RelocationTarget::ElfSpecific(reltype) => {
-                    obj_reltype = reltype;
-                    obj_relsym = 0;
-                    obj_addend = 0;
-                }
+        match relocation.target.clone() {
+            RelocationTarget::Symbol { symbol: symbol_name, addend } => {
+                obj_reltype = reloc_types.absolute;
+                obj_relsym = find_symbol(symbol_name);
+                obj_addend = addend;
+            },
+            RelocationTarget::Base { addend } => {
+                obj_reltype = reloc_types.relative;
+                obj_relsym = 0;
+                obj_addend = image_file_offset as i64 + addend;
+            },
+            RelocationTarget::Copy { symbol: symbol_name } => {
+                obj_reltype = reloc_types.copy;
+                obj_relsym = find_symbol(symbol_name);
+                obj_addend = 0;
+            },
+            RelocationTarget::IRelative { resolver } => {
+                obj_reltype = reloc_types.indirect;
+                obj_relsym = 0;
+                obj_addend = (image_file_offset as u64 + resolver) as i64;
+            },
+            RelocationTarget::TlsModule { symbol } => {
+                obj_reltype = reloc_types.tls_module;
+                obj_relsym = symbol.map(find_symbol).unwrap_or(0);
+                obj_addend = 0;
+            }
+            RelocationTarget::TlsOffset { symbol, addend } => {
+                obj_reltype = reloc_types.tls_offset;
+                obj_relsym = symbol.map(find_symbol).unwrap_or(0);
+                obj_addend = addend;
+            }
+            RelocationTarget::ThreadPointerOffset { symbol, addend } => {
+                obj_reltype = reloc_types.thread_pointer_offset;
+                obj_relsym = symbol.map(find_symbol).unwrap_or(0);
+                obj_addend = addend;
+            }
+            RelocationTarget::None => {
+                obj_reltype = reloc_types.none;
+                obj_relsym = 0;
+                obj_addend = 0;
+            }
+            RelocationTarget::ElfSpecific(reltype) => {
+                obj_reltype = reltype;
+                obj_relsym = 0;
+                obj_addend = 0;
+            }
+        }
+        if !is_rela {
+            if let RelocationTarget::Symbol { .. } | RelocationTarget::Base { .. } |
+                    RelocationTarget::IRelative { .. } | RelocationTarget::TlsOffset { .. } | RelocationTarget::ThreadPointerOffset { .. } =
+                    relocation.target {
+                implicit_addends.push((relocation.offset, obj_addend));
             }
-        } else {
-            unreachable!()
         }
         obj_writer.write_relocation(is_rela, &Rel {
             // In executables and shared libraries, relocations are applied at a virtual address.
@@ -456,16 +1093,46 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
     for (index, lifetimizer) in image.initializers.iter().chain(image.finalizers.iter()).enumerate() {
         obj_writer.write_relocation(is_rela, &Rel {
             // All DT_INIT/DT_INIT_ARRAY/DT_FINI_ARRAY/DT_FINI addresses must be relocated with the object.
-            r_offset: (obj_dt_init_array_offset + index * 8) as u64,
+            r_offset: (obj_dt_init_array_offset + index * word_size) as u64,
             r_sym: 0,
-            r_type: R_X86_64_RELATIVE,
+            r_type: reloc_types.relative,
             r_addend: (image_file_offset as u64 + *lifetimizer) as i64,
         });
     }
+    // The arrays are filled in even when the addends are explicit, to make them easier to inspect.
+    obj_writer.pad_until(obj_dt_init_array_offset);
+    for lifetimizer in image.initializers.iter().chain(image.finalizers.iter()) {
+        obj_writer.write(&word_bytes(image_file_offset as u64 + *lifetimizer));
+    }
     obj_writer.pad_until(obj_dt_fini_array_offset + obj_dt_fini_array_length);
-    if let Some(ref tls_data) = image.tls_image {
-        obj_writer.write(&tls_data);
+    if let Some(ref tls_image) = image.tls_image {
+        obj_writer.pad_until(obj_tls_offset);
+        obj_writer.write(&tls_image.data);
     }
+    if let Some(eh_frame) = out_eh_frame {
+        // All of the pointers are 32-bit offsets: relative to the field itself for the pointer to `.eh_frame`,
+        // and relative to the beginning of `.eh_frame_hdr` for the search table, which is what unwinders expect.
+        let eh_frame_offset = |addr: u64, base: usize| {
+            i32::try_from((image_file_offset as u64 + addr) as i64 - base as i64)
+                .map_err(|_| Error::UnsupportedFeature {
+                    image: image.display_image_name().to_owned(),
+                    feature: ".eh_frame_hdr pointer out of range".to_owned()
+                })
+        };
+        obj_writer.pad_until(obj_eh_frame_hdr_offset);
+        obj_writer.write(&[/*version=*/1, DW_EH_PE_PCREL | DW_EH_PE_SDATA4, DW_EH_PE_UDATA4,
+            DW_EH_PE_DATAREL | DW_EH_PE_SDATA4]);
+        obj_writer.write(&endian.write_i32_bytes(eh_frame_offset(eh_frame, obj_eh_frame_hdr_offset + 4)?));
+        obj_writer.write(&endian.write_u32_bytes(out_eh_frame_table.len() as u32));
+        for &(initial_location, fde) in out_eh_frame_table.iter() {
+            obj_writer.write(&endian.write_i32_bytes(eh_frame_offset(initial_location, obj_eh_frame_hdr_offset)?));
+            obj_writer.write(&endian.write_i32_bytes(eh_frame_offset(fde, obj_eh_frame_hdr_offset)?));
+        }
+    }
+    obj_writer.pad_until(obj_notes_offset);
+    obj_writer.write(&out_notes_data);
+    obj_writer.pad_until(obj_gnu_property_note_offset);
+    obj_writer.write(&out_gnu_property_note_data);
 
     // Write section headers.
     obj_writer.write_shstrtab();
@@ -473,8 +1140,13 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
     obj_writer.write_shstrtab_section_header();
     obj_writer.write_dynamic_section_header(obj_dynamic_offset as u64);
     obj_writer.write_dynstr_section_header(obj_dynstr_offset as u64);
-    obj_writer.write_dynsym_section_header(obj_dynsym_offset as u64, 1);
+    let obj_dynsym_local_count = symbols.iter().filter(|symbol| symbol.scope == SymbolScope::Local).count();
+    obj_writer.write_dynsym_section_header(obj_dynsym_offset as u64, 1 + obj_dynsym_local_count as u32);
     obj_writer.write_hash_section_header(obj_hash_offset as u64);
+    obj_writer.write_gnu_hash_section_header(obj_gnu_hash_offset as u64);
+    obj_writer.write_gnu_versym_section_header(obj_versym_offset as u64);
+    obj_writer.write_gnu_verdef_section_header(obj_verdef_offset as u64);
+    obj_writer.write_gnu_verneed_section_header(obj_verneed_offset as u64);
     obj_writer.write_section_header(&SectionHeader {
         name: Some(obj_reloc_dyn_section_name),
         sh_type: if is_rela { SHT_RELA } else { SHT_REL },
@@ -503,6 +1175,56 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
             });
         } else { unreachable!() }
     }
+    if let (Some(tls_image), Some((_obj_tls_section_index, obj_tls_section_name))) =
+            (&image.tls_image, obj_tls_section_index_name) {
+        obj_writer.write_section_header(&SectionHeader {
+            name: Some(obj_tls_section_name),
+            sh_type: SHT_PROGBITS,
+            sh_flags: (SHF_ALLOC | SHF_WRITE | SHF_TLS) as u64,
+            sh_addr: obj_tls_offset as u64,
+            sh_offset: obj_tls_offset as u64,
+            sh_size: tls_image.data.len() as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: tls_image.alignment,
+            sh_entsize: 0,
+        });
+    }
+    if let Some((_obj_eh_frame_hdr_section_index, obj_eh_frame_hdr_section_name)) =
+            obj_eh_frame_hdr_section_index_name {
+        obj_writer.write_section_header(&SectionHeader {
+            name: Some(obj_eh_frame_hdr_section_name),
+            sh_type: SHT_PROGBITS,
+            sh_flags: SHF_ALLOC as u64,
+            sh_addr: obj_eh_frame_hdr_offset as u64,
+            sh_offset: obj_eh_frame_hdr_offset as u64,
+            sh_size: obj_eh_frame_hdr_length as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 4,
+            sh_entsize: 0,
+        });
+    }
+    for (section_index_name, offset, size, align) in [
+        (obj_notes_section_index_name, obj_notes_offset, out_notes_data.len(), 4),
+        (obj_gnu_property_note_section_index_name, obj_gnu_property_note_offset, out_gnu_property_note_data.len(),
+            word_size),
+    ] {
+        if let Some((_obj_section_index, obj_section_name)) = section_index_name {
+            obj_writer.write_section_header(&SectionHeader {
+                name: Some(obj_section_name),
+                sh_type: SHT_NOTE,
+                sh_flags: SHF_ALLOC as u64,
+                sh_addr: offset as u64,
+                sh_offset: offset as u64,
+                sh_size: size as u64,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: align as u64,
+                sh_entsize: 0,
+            });
+        }
+    }
     for out_load_section in out_load_sections {
         let sh_flags = match out_load_section.mode {
             LoadMode::ReadOnly => SHF_ALLOC,
@@ -525,13 +1247,29 @@ pub fn emit_elf(image: &Image) -> object::write::Result<Vec<u8>> {
 
     // Write image segments.
     for segment in image.segments.iter() {
+        let mut data = std::borrow::Cow::from(&segment.data[..]);
+        for &(offset, addend) in implicit_addends.iter() {
+            if offset >= segment.addr && offset < segment.addr + segment.size {
+                let range_begin = (offset - segment.addr) as usize;
+                let range_end = range_begin + word_size;
+                let data = data.to_mut();
+                if data.len() < range_end {
+                    data.resize(range_end, 0);
+                }
+                data[range_begin..range_end].copy_from_slice(&word_bytes(addend as u64));
+            }
+        }
         obj_writer.pad_until(image_file_offset + segment.addr as usize);
-        obj_writer.write(segment.data.as_ref());
+        obj_writer.write(data.as_ref());
         obj_writer.pad_until(image_file_offset + segment.addr as usize + segment.size as usize);
     }
 
     // If the reserved amount and written amount are the same, the file is probably good.
     assert_eq!(obj_writer.reserved_len(), obj_writer.len());
 
+    let build_id_offset = obj_notes_offset + out_build_id_offset;
+    let build_id = sha1_smol::Sha1::from(&elf_data).digest().bytes();
+    elf_data[build_id_offset..build_id_offset + BUILD_ID_SIZE].copy_from_slice(&build_id);
+
     Ok(elf_data)
 }
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// An error that occurred while parsing, merging, or emitting images.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io { path: PathBuf, error: std::io::Error },
+    /// The manifest that describes the link is not valid.
+    Manifest { path: PathBuf, reason: String },
+    /// A dependency of the input could not be found.
+    MissingDependency { image: String, dependency: String },
+    /// The input is not a well-formed ELF file.
+    Parse { image: String, error: elf::parse::ParseError },
+    /// The input is a saved image, but it cannot be loaded.
+    InvalidSavedImage { path: PathBuf, reason: String },
+    /// The input is a well-formed ELF file, but its contents are inconsistent.
+    Malformed { image: String, reason: String },
+    /// The input is valid, but is for a machine that isn't implemented.
+    UnsupportedMachine { image: String, machine: u16 },
+    /// The input is valid, but uses a feature that isn't implemented.
+    UnsupportedFeature { image: String, feature: String },
+    /// The input is valid, but uses a relocation type that isn't implemented.
+    UnsupportedRelocation { image: String, offset: u64, r_type: u32 },
+    /// A relocation of the input cannot be applied as described.
+    InvalidRelocation { image: String, offset: u64, reason: String },
+    /// The inputs are valid, but cannot be merged together.
+    IncompatibleImages { source: String, target: String, reason: String },
+    /// An image defines the same symbol more than once.
+    DuplicateSymbol { image: String, symbol: String },
+    /// Both images define a symbol, and neither definition can take the place of the other.
+    SymbolConflict { source: String, target: String, symbol: String },
+    /// The images require different interpreters.
+    InterpreterConflict { source: String, target: String },
+    /// An image cannot be moved by an offset that does not keep its segments aligned.
+    MisalignedRebase { image: String, offset: u64, alignment: u64 },
+    /// A relocation has to be bound to a symbol of the merged image, but cannot be rewritten to refer to it locally.
+    LocalBinding { image: String, symbol: String, offset: u64 },
+    /// A copy relocation is bound to a symbol of the merged image, but the symbol's data cannot be copied.
+    CopyRelocation { image: String, symbol: String, offset: u64 },
+    /// The output could not be emitted.
+    Emit { image: String, error: object::write::Error },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } =>
+                write!(f, "{}: {}", path.display(), error),
+            Error::Manifest { path, reason } =>
+                write!(f, "{}: invalid manifest: {}", path.display(), reason),
+            Error::MissingDependency { image, dependency } =>
+                write!(f, "{}: cannot find dependency {:?}", image, dependency),
+            Error::Parse { image, error } =>
+                write!(f, "{}: cannot parse ELF file: {}", image, error),
+            Error::InvalidSavedImage { path, reason } =>
+                write!(f, "{}: cannot load saved image: {}", path.display(), reason),
+            Error::Malformed { image, reason } =>
+                write!(f, "{}: malformed ELF file: {}", image, reason),
+            Error::UnsupportedMachine { image, machine } =>
+                write!(f, "{}: unsupported machine: {}", image,
+                    elf::to_str::e_machine_to_str(*machine).unwrap_or("<unknown>")),
+            Error::UnsupportedFeature { image, feature } =>
+                write!(f, "{}: unsupported feature: {}", image, feature),
+            Error::UnsupportedRelocation { image, offset, r_type } =>
+                write!(f, "{}: unsupported relocation type {} at {:#x}", image, r_type, offset),
+            Error::InvalidRelocation { image, offset, reason } =>
+                write!(f, "{}: invalid relocation at {:#x}: {}", image, offset, reason),
+            Error::IncompatibleImages { source, target, reason } =>
+                write!(f, "cannot merge {} into {}: {}", source, target, reason),
+            Error::DuplicateSymbol { image, symbol } =>
+                write!(f, "{}: duplicate symbol {:?}", image, symbol),
+            Error::SymbolConflict { source, target, symbol } =>
+                write!(f, "cannot merge symbol {:?} from {} into {}", symbol, source, target),
+            Error::InterpreterConflict { source, target } =>
+                write!(f, "cannot merge {} into {}: interpreters are incompatible", source, target),
+            Error::MisalignedRebase { image, offset, alignment } =>
+                write!(f, "{}: cannot rebase by {:#x}, which is not a multiple of the alignment {:#x}", image, offset,
+                    alignment),
+            Error::LocalBinding { image, symbol, offset } =>
+                write!(f, "{}: cannot bind relocation at {:#x} to non-default visibility symbol {:?} locally", image,
+                    offset, symbol),
+            Error::CopyRelocation { image, symbol, offset } =>
+                write!(f, "{}: cannot apply copy relocation for symbol {:?} at {:#x}", image, symbol, offset),
+            Error::Emit { image, error } =>
+                write!(f, "{}: cannot emit ELF file: {}", image, error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            Error::Parse { error, .. } => Some(error),
+            Error::Emit { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
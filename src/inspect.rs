@@ -0,0 +1,123 @@
+use superlinker::{Image, Interpreter, LoadMode, RelocationTarget, Symbol, SymbolScope};
+
+fn mode_name(mode: LoadMode) -> &'static str {
+    match mode {
+        LoadMode::ReadOnly => "r--",
+        LoadMode::ReadWrite => "rw-",
+        LoadMode::ReadExecute => "r-x",
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn signed_hex(value: i64) -> String {
+    if value < 0 { format!("-{:#x}", value.unsigned_abs()) } else { format!("{:#x}", value) }
+}
+
+// Each relocation target is shown as its kind, the symbol it refers to (if any), and the number that goes with it
+// (the addend, or the resolver for IRELATIVE, or the ELF relocation type for the ones that aren't lifted).
+fn relocation_fields(target: &RelocationTarget) -> (&'static str, Option<&str>, Option<String>) {
+    match target {
+        RelocationTarget::Symbol { symbol, addend } => ("symbol", Some(symbol), Some(signed_hex(*addend))),
+        RelocationTarget::Base { addend } => ("base", None, Some(signed_hex(*addend))),
+        RelocationTarget::Copy { symbol } => ("copy", Some(symbol), None),
+        RelocationTarget::TlsModule { symbol } => ("tls_module", symbol.as_deref(), None),
+        RelocationTarget::TlsOffset { symbol, addend } =>
+            ("tls_offset", symbol.as_deref(), Some(signed_hex(*addend))),
+        RelocationTarget::ThreadPointerOffset { symbol, addend } =>
+            ("thread_pointer_offset", symbol.as_deref(), Some(signed_hex(*addend))),
+        RelocationTarget::IRelative { resolver } => ("irelative", None, Some(format!("{:#x}", resolver))),
+        RelocationTarget::None => ("none", None, None),
+        RelocationTarget::ElfSpecific(r_type) => ("elf_specific", None, Some(r_type.to_string())),
+    }
+}
+
+fn symbol_scope_name(symbol: &Symbol) -> &'static str {
+    match symbol.scope {
+        SymbolScope::Local => "local",
+        SymbolScope::Global => "global",
+        SymbolScope::Import => "import",
+        SymbolScope::Weak if symbol.value == 0 => "weak import",
+        SymbolScope::Weak => "weak",
+    }
+}
+
+fn lowercase_name(value: impl std::fmt::Debug) -> String {
+    format!("{:?}", value).to_lowercase()
+}
+
+/// Prints the image as a set of tables, one for each kind of entity in it.
+pub fn print_table(image: &Image) {
+    let list = |items: &[String]| if items.is_empty() { "-".to_owned() } else { items.join(" ") };
+    println!("{}", image.display_image_name());
+    println!("  machine        {}", elf::to_str::e_machine_to_str(image.machine).unwrap_or("<unknown>"));
+    println!("  word size      {}-bit", image.word_size.bytes() * 8);
+    println!("  byte order     {}", lowercase_name(image.byte_order));
+    println!("  alignment      {:#x}", image.alignment);
+    println!("  entry          {:#x}", image.entry);
+    match &image.interpreter {
+        Interpreter::Absent => println!("  interpreter    -"),
+        Interpreter::External(path) => println!("  interpreter    {}", path),
+        Interpreter::Internal { base, entry, segments } =>
+            println!("  interpreter    internal, based at {:#x}, entry {:#x}, {} segments", base, entry, segments),
+    }
+    println!("  names          {}", list(&image.image_names));
+    println!("  dependencies   {}", list(&image.dependencies));
+    println!("  runpaths       {}", list(&image.runpaths));
+
+    println!();
+    println!("  {:<18} {:<18} {:<18} MODE", "ADDR", "SIZE", "DATA SIZE");
+    for segment in image.segments.iter() {
+        println!("  {:<18} {:<18} {:<18} {}", format!("{:#x}", segment.addr), format!("{:#x}", segment.size),
+            format!("{:#x}", segment.data.len()), mode_name(segment.mode));
+    }
+    println!();
+    if let Some(tls_image) = &image.tls_image {
+        println!("  tls            {:#x} bytes, aligned to {:#x}", tls_image.data.len(), tls_image.alignment);
+    }
+    for relro_range in image.relro_ranges.iter() {
+        println!("  relro          {:#x}..{:#x} of {}", relro_range.addr, relro_range.addr + relro_range.size,
+            relro_range.image_name);
+    }
+    for eh_frame_hdr in image.eh_frame_hdrs.iter() {
+        println!("  eh_frame_hdr   {:#x}..{:#x} of {}", eh_frame_hdr.addr, eh_frame_hdr.addr + eh_frame_hdr.size,
+            eh_frame_hdr.image_name);
+    }
+    for stack_requirement in image.stack_requirements.iter() {
+        println!("  stack          {} of {}, size {:#x}",
+            if stack_requirement.executable { "executable" } else { "non-executable" },
+            stack_requirement.image_name, stack_requirement.size);
+    }
+    for note in image.notes.iter() {
+        println!("  note           {} type {:#x} of {}: {}", note.name, note.note_type, note.image_name,
+            hex_bytes(&note.desc));
+    }
+    for property in image.gnu_properties.iter() {
+        println!("  gnu property   {:#x}: {}", property.property_type, hex_bytes(&property.data));
+    }
+
+    println!();
+    println!("  {:<18} {:<10} {:<7} {:<11} {:<9} NAME", "VALUE", "SIZE", "KIND", "SCOPE", "VIS");
+    for symbol in image.symbols.iter() {
+        let value = if symbol.abs { format!("{:#x} (abs)", symbol.value) } else { format!("{:#x}", symbol.value) };
+        println!("  {:<18} {:<10} {:<7} {:<11} {:<9} {}", value, format!("{:#x}", symbol.size),
+            lowercase_name(symbol.kind), symbol_scope_name(symbol), lowercase_name(symbol.visibility),
+            symbol.versioned_name());
+    }
+
+    println!();
+    println!("  {:<18} {:<21} {:<18} SYMBOL", "OFFSET", "TARGET", "VALUE");
+    for relocation in image.relocations.iter() {
+        let (kind, symbol, value) = relocation_fields(&relocation.target);
+        println!("  {:<18} {:<21} {:<18} {}", format!("{:#x}", relocation.offset), kind,
+            value.unwrap_or_else(|| "-".to_owned()), symbol.unwrap_or("-"));
+    }
+    for initializer in image.initializers.iter() {
+        println!("  {:<18} initializer", format!("{:#x}", initializer));
+    }
+    for finalizer in image.finalizers.iter() {
+        println!("  {:<18} finalizer", format!("{:#x}", finalizer));
+    }
+}
@@ -0,0 +1,31 @@
+//! Superlinker combines several dynamically linked ELF images into a single one.
+//!
+//! An ELF file is lifted into an [`Image`] with [`parse_elf`], any number of other images are combined with it using
+//! [`Image::merge_into`], and the result is lowered back into an ELF file with [`emit_elf`]. The images to merge can
+//! be listed by hand, or found by following the dependencies of an executable with [`load_closure`].
+//!
+//! Decisions made along the way, such as how each symbol is resolved, are reported through the [`log`] crate.
+//! Symbol resolution decisions are logged at the debug level with the `symbol`, `source_image`, `target_image`,
+//! and `provider` (`source`, `target`, or `local`) keys.
+
+mod error;
+mod repr;
+mod parse;
+mod emit;
+mod resolve;
+mod manifest;
+mod save;
+
+pub use elf::endian::{AnyEndian, BigEndian, EndianParse, LittleEndian};
+
+pub use error::Error;
+pub use repr::{
+    ByteOrder, WordSize, LoadMode, LoadSegment, AddressRange, ImageRange, StackRequirement, Note, GnuProperty, TlsImage,
+    SymbolKind, SymbolScope, SymbolVisibility, SymbolVersion, Symbol, RelocationTarget, Relocation, Interpreter, Image,
+    versioned_symbol_name,
+};
+pub use parse::parse_elf;
+pub use emit::{HashStyle, EmitOptions, emit_elf, ELF_NOTE_SUPERLINKER, NT_SUPERLINKER_SOURCE_BUILD_IDS};
+pub use resolve::{SearchPaths, load_image, load_closure};
+pub use manifest::{Manifest, SymbolPolicies};
+pub use save::{SaveFormat, SAVED_IMAGE_VERSION, is_saved_image};
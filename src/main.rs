@@ -1,10 +1,53 @@
-use elf::endian::AnyEndian;
+mod inspect;
 
-mod repr;
-mod parse;
-mod emit;
+use std::path::{Path, PathBuf};
 
-fn make_executable<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<()> {
+use log::{info, LevelFilter, Log, Metadata, Record};
+
+use superlinker::{Error, Image, Manifest, SaveFormat, SearchPaths, EmitOptions, HashStyle};
+
+const USAGE: &str = "\
+Usage: superlinker [link] [<options>] -o <output.elf> <target.elf> [<source.elf>...]
+       superlinker [link] [<options>] <output.elf> <target.elf> [<source.elf>...]
+       superlinker verify [<options>] <target.elf> [<source.elf>...]
+       superlinker inspect [--json] [<options>] <input.elf>...
+       superlinker save [--json] [<options>] -o <output.img> <target.elf> [<source.elf>...]
+       superlinker [link|verify|inspect|save] [-o <output>] [<options>] -m <manifest>
+
+Commands:
+    link              merge the sources into the target and write the result (the default)
+    verify            merge the sources into the target and check the result, without writing it
+    inspect           describe the inputs as Superlinker sees them, as tables or as JSON (with --json)
+    save              merge the sources into the target and save the result as an image, in a binary form or
+                      as JSON (with --json), which can be used as an input instead of an ELF file later
+
+Options:
+    -o, --output <file>
+                      write the result to <file>
+    -m, --manifest <file>
+                      read the inputs and the options of the link from <file> (TOML, or JSON if it ends in .json),
+                      instead of from the command line
+    --resolve         also merge the dependencies of the inputs, and the interpreter of the target
+    --sysroot <dir>   look for dependencies and the interpreter in <dir> instead of /
+    -L <dir>          look for dependencies in <dir> before any other directories
+    --hash-style <style>
+                      emit DT_HASH (sysv), DT_GNU_HASH (gnu), or both (the default)
+    --noexecstack     make the stack non-executable even if some of the inputs require it to be executable
+    --allow-partial-relro
+                      leave writable the RELRO ranges of the inputs that cannot be coalesced with the largest one,
+                      instead of failing
+    -v, --verbose     report more details; repeat (-vv) to report everything
+    -q, --quiet       only report warnings and errors
+    --log <spec>      report messages up to a level (error, warn, info, debug, or trace), either everywhere,
+                      or in a module, e.g. `--log repr=debug`; can be repeated
+    --log-format <format>
+                      report messages as text (the default) or as JSON lines (json)
+    --json            describe the inputs as JSON instead of as tables (for inspect), or save the image
+                      as JSON instead of in a binary form (for save)
+    -h, --help        print this message
+    @<file>           read further arguments from <file>, separated by whitespace";
+
+fn make_executable<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
     use std::os::unix::fs::PermissionsExt;
 
     let mut perms = std::fs::metadata(&path)?.permissions();
@@ -13,23 +56,601 @@ fn make_executable<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<()> {
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+// Messages are filtered by the module they come from (e.g. `superlinker::repr`), using the most specific filter
+// that applies to the module.
+struct Logger {
+    default_level: LevelFilter,
+    module_levels: Vec<(String, LevelFilter)>,
+    format: LogFormat,
+}
+
+impl Logger {
+    fn max_level(&self) -> LevelFilter {
+        self.module_levels.iter().map(|&(_, level)| level).fold(self.default_level, Ord::max)
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.module_levels.iter()
+            .filter(|(module, _)| target == module || target.strip_prefix(&module[..])
+                .is_some_and(|suffix| suffix.starts_with("::")))
+            .max_by_key(|(module, _)| module.len())
+            .map(|&(_, level)| level)
+            .unwrap_or(self.default_level)
+    }
+}
+
+// Each JSON line has the level, module, and message of the record, as well as its structured data, if any
+// (such as the symbol and the images involved in a symbol resolution decision).
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+        self.0.insert(key.as_str().to_owned(), value.to_string().into());
+        Ok(())
+    }
+}
+
+fn json_record(record: &Record) -> serde_json::Value {
+    let mut fields = JsonFields(serde_json::Map::new());
+    fields.0.insert("level".to_owned(), record.level().as_str().to_lowercase().into());
+    fields.0.insert("module".to_owned(), record.target().into());
+    fields.0.insert("message".to_owned(), record.args().to_string().into());
+    let _ = record.key_values().visit(&mut fields);
+    serde_json::Value::Object(fields.0)
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) { return }
+        match self.format {
+            LogFormat::Text => {
+                let level = match record.level() {
+                    log::Level::Error => "error",
+                    log::Level::Warn => "warning",
+                    log::Level::Info => "info",
+                    log::Level::Debug => "debug",
+                    log::Level::Trace => "trace",
+                };
+                eprintln!("{}: {}", level, record.args());
+            }
+            LogFormat::Json => eprintln!("{}", json_record(record)),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+enum Command {
+    Link { output_filename: PathBuf },
+    Verify,
+    Inspect { json: bool },
+    Save { output_filename: PathBuf, format: SaveFormat },
+    Help,
+}
+
+struct Options {
+    command: Command,
+    resolve: bool,
+    search_paths: SearchPaths,
+    emit_options: EmitOptions,
+    input_filenames: Vec<PathBuf>, // target first, then sources
+    manifest: Option<Manifest>,
+}
+
+// Response files contain arguments separated by whitespace. Arguments that contain whitespace can be quoted with
+// single or double quotes, or the whitespace can be escaped with a backslash, like in the shell. Response files
+// can refer to other response files.
+fn expand_response_files(args: impl Iterator<Item = String>, depth: usize) -> Result<Vec<String>, String> {
+    let mut expanded_args = Vec::new();
+    for arg in args {
+        let Some(path) = arg.strip_prefix('@') else {
+            expanded_args.push(arg);
+            continue
+        };
+        if depth == 16 {
+            return Err(format!("{}: response files are nested too deeply", path))
+        }
+        let contents = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let mut file_args = Vec::new();
+        let mut file_arg: Option<String> = None;
+        let mut quote = None;
+        let mut chars = contents.chars();
+        while let Some(char) = chars.next() {
+            match (quote, char) {
+                (Some(quote_char), _) if char == quote_char => quote = None,
+                (Some('\''), _) => file_arg.get_or_insert_with(String::new).push(char),
+                (_, '\\') => match chars.next() {
+                    Some(escaped_char) => file_arg.get_or_insert_with(String::new).push(escaped_char),
+                    None => return Err(format!("{}: unterminated escape", path)),
+                },
+                (Some(_), _) => file_arg.get_or_insert_with(String::new).push(char),
+                (None, '\'' | '"') => {
+                    file_arg.get_or_insert_with(String::new);
+                    quote = Some(char);
+                }
+                (None, _) if char.is_whitespace() => file_args.extend(file_arg.take()),
+                (None, _) => file_arg.get_or_insert_with(String::new).push(char),
+            }
+        }
+        if quote.is_some() {
+            return Err(format!("{}: unterminated quote", path))
+        }
+        file_args.extend(file_arg);
+        expanded_args.extend(expand_response_files(file_args.into_iter(), depth + 1)?);
+    }
+    Ok(expanded_args)
+}
+
+// Mistakes on the command line are reported together with the usage, but a manifest that cannot be loaded is reported
+// like any other input that cannot be read.
+enum OptionsError {
+    Usage(String),
+    Manifest(Error),
+}
+
+impl From<String> for OptionsError {
+    fn from(message: String) -> Self {
+        OptionsError::Usage(message)
+    }
+}
+
+impl From<&str> for OptionsError {
+    fn from(message: &str) -> Self {
+        OptionsError::Usage(message.to_owned())
+    }
+}
+
+fn parse_options(args: impl Iterator<Item = String>) -> Result<(Options, Logger), OptionsError> {
+    let mut args = expand_response_files(args, 0)?.into_iter().peekable();
+    let command_name = match args.peek().map(|arg| &arg[..]) {
+        Some(name @ ("link" | "verify" | "inspect" | "save" | "help")) => { let name = name.to_owned(); args.next(); name }
+        _ => "link".to_owned()
+    };
+    let mut logger = Logger { default_level: LevelFilter::Info, module_levels: Vec::new(), format: LogFormat::Text };
+    let mut resolve = false;
+    let mut search_paths = SearchPaths::default();
+    let mut emit_options = EmitOptions::default();
+    let mut output_filename = None;
+    let mut filenames = Vec::new();
+    let mut help = command_name == "help";
+    let mut manifest_filename = None;
+    let mut has_link_options = false;
+    let mut json = false;
+    let missing = |option: &str| format!("option {} requires an argument", option);
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            help = true;
+        } else if arg == "-v" || arg == "--verbose" || arg == "-vv" {
+            for _ in 0..if arg == "-vv" { 2 } else { 1 } {
+                logger.default_level = logger.default_level.increment_severity();
+            }
+        } else if arg == "-q" || arg == "--quiet" {
+            logger.default_level = LevelFilter::Warn;
+        } else if arg == "--log" {
+            parse_log_spec(&mut logger, &args.next().ok_or_else(|| missing(&arg))?)?;
+        } else if let Some(spec) = arg.strip_prefix("--log=") {
+            parse_log_spec(&mut logger, spec)?;
+        } else if arg == "--log-format" {
+            logger.format = parse_log_format(&args.next().ok_or_else(|| missing(&arg))?)?;
+        } else if let Some(format) = arg.strip_prefix("--log-format=") {
+            logger.format = parse_log_format(format)?;
+        } else if arg == "-o" || arg == "--output" {
+            output_filename = Some(PathBuf::from(args.next().ok_or_else(|| missing(&arg))?));
+        } else if let Some(filename) = arg.strip_prefix("--output=") {
+            output_filename = Some(PathBuf::from(filename));
+        } else if let Some(filename) = arg.strip_prefix("-o") {
+            output_filename = Some(PathBuf::from(filename));
+        } else if arg == "--json" {
+            json = true;
+        } else if arg == "-m" || arg == "--manifest" {
+            manifest_filename = Some(PathBuf::from(args.next().ok_or_else(|| missing(&arg))?));
+        } else if let Some(filename) = arg.strip_prefix("--manifest=") {
+            manifest_filename = Some(PathBuf::from(filename));
+        } else if arg == "--resolve" {
+            has_link_options = true;
+            resolve = true;
+        } else if arg == "--sysroot" {
+            has_link_options = true;
+            search_paths.sysroot = PathBuf::from(args.next().ok_or_else(|| missing(&arg))?);
+        } else if let Some(dir) = arg.strip_prefix("--sysroot=") {
+            has_link_options = true;
+            search_paths.sysroot = PathBuf::from(dir);
+        } else if arg == "-L" {
+            has_link_options = true;
+            search_paths.library_dirs.push(PathBuf::from(args.next().ok_or_else(|| missing(&arg))?));
+        } else if let Some(dir) = arg.strip_prefix("-L") {
+            has_link_options = true;
+            search_paths.library_dirs.push(PathBuf::from(dir));
+        } else if arg == "--hash-style" {
+            has_link_options = true;
+            emit_options.hash_style = parse_hash_style(&args.next().ok_or_else(|| missing(&arg))?)?;
+        } else if let Some(style) = arg.strip_prefix("--hash-style=") {
+            has_link_options = true;
+            emit_options.hash_style = parse_hash_style(style)?;
+        } else if arg == "--noexecstack" {
+            has_link_options = true;
+            emit_options.force_non_executable_stack = true;
+        } else if arg == "--allow-partial-relro" {
+            has_link_options = true;
+            emit_options.allow_partial_relro = true;
+        } else if arg == "--" {
+            filenames.extend(args.by_ref().map(PathBuf::from));
+        } else if arg.starts_with('-') {
+            return Err(format!("unknown option {}", arg).into())
+        } else {
+            filenames.push(PathBuf::from(arg));
+        }
+    }
+    // A manifest replaces the inputs and the options of the link, so that it describes the link completely; only
+    // the output can be overridden. The output of the manifest is ignored by the commands that do not write one,
+    // but `-o` is rejected for them all the same.
+    let manifest = match manifest_filename {
+        Some(_) if has_link_options || !filenames.is_empty() =>
+            return Err("inputs and link options cannot be combined with a manifest".into()),
+        Some(manifest_filename) => {
+            let manifest = Manifest::load(&manifest_filename).map_err(OptionsError::Manifest)?;
+            resolve = manifest.resolve;
+            search_paths = manifest.search_paths();
+            emit_options = manifest.emit_options();
+            filenames = manifest.inputs();
+            if command_name == "link" || command_name == "save" {
+                output_filename = output_filename.or_else(|| manifest.output.clone());
+            }
+            Some(manifest)
+        }
+        None => None
+    };
+    let command = match &command_name[..] {
+        _ if help => Command::Help,
+        "link" | "verify" if json => return Err("option --json is only valid for inspect and save".into()),
+        "link" => {
+            // Without `-o`, the output is the first file name, which is how Superlinker was originally invoked.
+            let output_filename = match output_filename {
+                Some(output_filename) => output_filename,
+                None if !filenames.is_empty() => filenames.remove(0),
+                None => return Err("no output file".into()),
+            };
+            Command::Link { output_filename }
+        }
+        "save" => {
+            let output_filename = output_filename.ok_or("no output file")?;
+            Command::Save { output_filename, format: if json { SaveFormat::Json } else { SaveFormat::Binary } }
+        }
+        "verify" | "inspect" if output_filename.is_some() =>
+            return Err(format!("command {} does not write an output file", command_name).into()),
+        "verify" => Command::Verify,
+        "inspect" => Command::Inspect { json },
+        _ => unreachable!()
+    };
+    if filenames.is_empty() && !matches!(command, Command::Help) {
+        return Err("no input files".into())
+    }
+    Ok((Options { command, resolve, search_paths, emit_options, input_filenames: filenames, manifest }, logger))
+}
+
+fn parse_hash_style(style: &str) -> Result<HashStyle, String> {
+    match style {
+        "sysv" => Ok(HashStyle::Sysv),
+        "gnu" => Ok(HashStyle::Gnu),
+        "both" => Ok(HashStyle::Both),
+        _ => Err(format!("unknown hash style {:?}", style))
+    }
+}
+
+// The spec is either a level, or `<module>=<level>`, where the module is relative to the `superlinker` crate.
+fn parse_log_spec(logger: &mut Logger, spec: &str) -> Result<(), String> {
+    let parse_level = |level: &str| level.parse::<LevelFilter>().map_err(|_| format!("unknown log level {:?}", level));
+    match spec.split_once('=') {
+        Some((module, level)) => {
+            let module = match module {
+                "superlinker" => module.to_owned(),
+                _ if module.starts_with("superlinker::") => module.to_owned(),
+                _ => format!("superlinker::{}", module),
+            };
+            logger.module_levels.push((module, parse_level(level)?));
+        }
+        None => logger.default_level = parse_level(spec)?,
+    }
+    Ok(())
+}
+
+fn parse_log_format(format: &str) -> Result<LogFormat, String> {
+    match format {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => Err(format!("unknown log format {:?}", format))
+    }
+}
+
+fn load(options: &Options) -> Result<Vec<Image>, Error> {
+    let images = if options.resolve {
+        superlinker::load_closure(&options.input_filenames, &options.search_paths)?
+    } else {
+        options.input_filenames.iter()
+            .map(|filename| superlinker::load_image(filename))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    for image in images.iter() {
+        log::debug!("superlinker: loaded image {}", image.display_image_name());
+    }
+    Ok(images)
+}
+
+fn merge(options: &Options) -> Result<Image, Error> {
+    let mut images = load(options)?.into_iter();
+    let mut target_image = images.next().expect("at least one input");
+    for source_image in images {
+        source_image.merge_into(&mut target_image)?;
+    }
+    if let Some(manifest) = &options.manifest {
+        manifest.apply(&mut target_image);
+    }
+    Ok(target_image)
+}
+
+fn link(options: &Options) -> Result<Vec<u8>, Error> {
+    superlinker::emit_elf(&merge(options)?, &options.emit_options)
+}
+
+fn run(options: &Options) -> Result<(), Error> {
+    match &options.command {
+        Command::Link { output_filename } => {
+            let output_data = link(options)?;
+            let io_error = |error| Error::Io { path: output_filename.clone(), error };
+            std::fs::write(output_filename, &output_data).map_err(io_error)?;
+            make_executable(output_filename).map_err(io_error)?;
+            info!("superlinker: wrote {} bytes to {}", output_data.len(), output_filename.display());
+        }
+        // The output is parsed again to check that it is at least as well-formed as Superlinker can tell.
+        Command::Verify => {
+            let output_data = link(options)?;
+            superlinker::parse_elf::<superlinker::AnyEndian>(&output_data, None)?;
+            info!("superlinker: inputs can be linked");
+        }
+        Command::Inspect { json: false } => {
+            for (index, image) in load(options)?.iter().enumerate() {
+                if index > 0 { println!() }
+                inspect::print_table(image);
+            }
+        }
+        // Each input is described the same as if it was saved on its own, so that the two can be compared, and
+        // the description can be loaded as an input.
+        Command::Inspect { json: true } => {
+            for image in load(options)? {
+                print!("{}", String::from_utf8(image.save(SaveFormat::Json)).expect("saved images are UTF-8"));
+            }
+        }
+        Command::Save { output_filename, format } => {
+            let output_data = merge(options)?.save(*format);
+            let io_error = |error| Error::Io { path: output_filename.clone(), error };
+            std::fs::write(output_filename, &output_data).map_err(io_error)?;
+            info!("superlinker: saved image to {}", output_filename.display());
+        }
+        Command::Help => println!("{}", USAGE),
+    }
+    Ok(())
+}
+
+// Exit codes are grouped by the kind of failure, so that scripts can tell them apart.
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::Io { .. } | Error::MissingDependency { .. } => 2,
+        Error::Parse { .. } | Error::InvalidSavedImage { .. } | Error::Malformed { .. } |
+        Error::InvalidRelocation { .. } => 3,
+        Error::UnsupportedMachine { .. } | Error::UnsupportedFeature { .. } |
+        Error::UnsupportedRelocation { .. } => 4,
+        Error::IncompatibleImages { .. } | Error::DuplicateSymbol { .. } | Error::SymbolConflict { .. } |
+        Error::InterpreterConflict { .. } | Error::MisalignedRebase { .. } | Error::LocalBinding { .. } |
+        Error::CopyRelocation { .. } => 5,
+        Error::Emit { .. } => 6,
+        Error::Manifest { .. } => 7,
+    }
+}
+
 fn main() {
-    let output_filename = std::path::PathBuf::from(std::env::args().nth(1).expect("Usage: $0 <output.elf> <input.elf> <merge.elf>..."));
-    let input_filename = std::path::PathBuf::from(std::env::args().nth(2).expect("Usage: $0 <output.elf> <input.elf> <merge.elf>..."));
-    let merge_filenames = std::env::args().skip(3).map(|arg| std::path::PathBuf::from(arg));
+    let (options, logger) = match parse_options(std::env::args().skip(1)) {
+        Ok(options_logger) => options_logger,
+        Err(OptionsError::Usage(message)) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            std::process::exit(1);
+        }
+        Err(OptionsError::Manifest(error)) => {
+            eprintln!("error: {}", error);
+            std::process::exit(exit_code(&error));
+        }
+    };
+
+    let logger = Box::leak(Box::new(logger));
+    log::set_max_level(logger.max_level());
+    log::set_logger(logger).expect("no other logger");
+
+    if let Err(error) = run(&options) {
+        eprintln!("error: {}", error);
+        std::process::exit(exit_code(&error));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The only way the ELF writer fails is by not being able to allocate its buffer.
+    struct UnallocatableBuffer;
+
+    impl object::write::WritableBuffer for UnallocatableBuffer {
+        fn len(&self) -> usize { 0 }
+        fn reserve(&mut self, _size: usize) -> Result<(), ()> { Err(()) }
+        fn resize(&mut self, _new_len: usize) {}
+        fn write_bytes(&mut self, _val: &[u8]) {}
+    }
+
+    fn emit_error() -> object::write::Error {
+        let mut buffer = UnallocatableBuffer;
+        let mut writer = object::write::elf::Writer::new(object::Endianness::Little, true, &mut buffer);
+        writer.write_file_header(&object::write::elf::FileHeader {
+            os_abi: 0,
+            abi_version: 0,
+            e_type: 0,
+            e_machine: 0,
+            e_entry: 0,
+            e_flags: 0,
+        }).unwrap_err()
+    }
+
+    // A directory of its own for each test, since tests run in parallel.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("superlinker-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_file(dir: &Path, name: &str, contents: &str) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
 
-    let input_data = std::fs::read(&input_filename).expect("Could not read input file");
-    let input_soname = input_filename.file_name().and_then(|name| name.to_str());
-    let mut input_image = parse::parse_elf::<AnyEndian>(&input_data[..], input_soname).expect("Could not parse input file");
+    fn expand(args: &[&str]) -> Result<Vec<String>, String> {
+        expand_response_files(args.iter().map(|&arg| arg.to_owned()), 0)
+    }
+
+    #[test]
+    fn response_files() {
+        let dir = test_dir("response_files");
+        let quoted = write_file(&dir, "quoted", "a \"b c\" 'd\\e' f\\ g \"h\\\"i\" '' \n\t j");
+        assert_eq!(expand(&["-v", &format!("@{}", quoted), "k"]).unwrap(),
+            ["-v", "a", "b c", "d\\e", "f g", "h\"i", "", "j", "k"]);
+        let inner = write_file(&dir, "inner", "b");
+        let outer = write_file(&dir, "outer", &format!("a @{} c", inner));
+        assert_eq!(expand(&[&format!("@{}", outer)]).unwrap(), ["a", "b", "c"]);
+        let unterminated_quote = write_file(&dir, "unterminated_quote", "a \"b");
+        assert_eq!(expand(&[&format!("@{}", unterminated_quote)]).unwrap_err(),
+            format!("{}: unterminated quote", unterminated_quote));
+        let unterminated_escape = write_file(&dir, "unterminated_escape", "a \\");
+        assert_eq!(expand(&[&format!("@{}", unterminated_escape)]).unwrap_err(),
+            format!("{}: unterminated escape", unterminated_escape));
+    }
+
+    #[test]
+    fn response_file_depth() {
+        let dir = test_dir("response_file_depth");
+        // Each file refers to the next one, so starting from the first, the last one is nested 16 levels deep.
+        let mut path = write_file(&dir, "17", "17");
+        for index in (1..17).rev() {
+            path = write_file(&dir, &index.to_string(), &format!("{} @{}", index, path));
+        }
+        let second = dir.join("2").to_str().unwrap().to_owned();
+        let expected = (2..=17).map(|index| index.to_string()).collect::<Vec<_>>();
+        assert_eq!(expand(&[&format!("@{}", second)]).unwrap(), expected);
+        assert_eq!(expand(&[&format!("@{}", path)]).unwrap_err(),
+            format!("{}: response files are nested too deeply", dir.join("17").display()));
+    }
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        match parse_options(args.iter().map(|&arg| arg.to_owned())) {
+            Ok((options, _logger)) => Ok(options),
+            Err(OptionsError::Usage(message)) => Err(message),
+            Err(OptionsError::Manifest(error)) => Err(error.to_string()),
+        }
+    }
+
+    #[test]
+    fn manifest_options() {
+        let dir = test_dir("manifest_options");
+        let manifest = write_file(&dir, "superlinker.toml", "output = \"out\"\ntarget = \"fixture\"\n");
+        match parse(&["link", "-m", &manifest]).unwrap().command {
+            Command::Link { output_filename } => assert_eq!(output_filename, dir.join("out")),
+            _ => panic!("unexpected command"),
+        }
+        match parse(&["save", "-o", "other", "-m", &manifest]).unwrap().command {
+            Command::Save { output_filename, .. } => assert_eq!(output_filename, PathBuf::from("other")),
+            _ => panic!("unexpected command"),
+        }
+        // The output of the manifest is only ignored by the commands that do not write one.
+        assert!(matches!(parse(&["verify", "-m", &manifest]).unwrap().command, Command::Verify));
+        assert!(matches!(parse(&["inspect", "-m", &manifest]).unwrap().command, Command::Inspect { json: false }));
+        for args in [&["verify", "-o", "other", "-m", &manifest][..], &["inspect", "-o", "other", "fixture"]] {
+            assert_eq!(parse(args).err().unwrap(), format!("command {} does not write an output file", args[0]));
+        }
+        for args in [&["-m", &manifest, "--resolve"][..], &["-m", &manifest, "-Llib"], &["-m", &manifest, "input"]] {
+            assert_eq!(parse(args).err().unwrap(), "inputs and link options cannot be combined with a manifest");
+        }
+    }
 
-    for merge_filename in merge_filenames {
-        let merge_data = std::fs::read(&merge_filename).expect("Could not read merge file");
-        let merge_soname = merge_filename.file_name().and_then(|name| name.to_str());
-        let merge_image = parse::parse_elf::<AnyEndian>(&merge_data[..], merge_soname).expect("Could not parse merge file");
-        merge_image.merge_into(&mut input_image);
+    #[test]
+    fn log_levels() {
+        let logger = Logger {
+            default_level: LevelFilter::Info,
+            module_levels: vec![
+                ("superlinker".to_owned(), LevelFilter::Warn),
+                ("superlinker::repr".to_owned(), LevelFilter::Trace),
+                ("superlinker::re".to_owned(), LevelFilter::Error),
+            ],
+            format: LogFormat::Text,
+        };
+        assert_eq!(logger.level_for("superlinker::repr"), LevelFilter::Trace);
+        assert_eq!(logger.level_for("superlinker::repr::merge"), LevelFilter::Trace);
+        // Filters apply to whole module names only.
+        assert_eq!(logger.level_for("superlinker::resolve"), LevelFilter::Warn);
+        assert_eq!(logger.level_for("superlinker"), LevelFilter::Warn);
+        assert_eq!(logger.level_for("superlinker_other"), LevelFilter::Info);
+        assert_eq!(logger.level_for("object"), LevelFilter::Info);
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
     }
 
-    let output_data = emit::emit_elf(&input_image).expect("Could not emit output file");
-    std::fs::write(&output_filename, output_data).expect("Could not write output file");
-    make_executable(&output_filename).expect("Could not make output file executable");
+    #[test]
+    fn json_log_record() {
+        let key_values = [("symbol", "memcpy"), ("provider", "source")];
+        let record = json_record(&Record::builder()
+            .level(log::Level::Debug)
+            .target("superlinker::repr")
+            .args(format_args!("merge_into: using source symbol {:?}", "memcpy"))
+            .key_values(&key_values)
+            .build());
+        assert_eq!(record, serde_json::json!({
+            "level": "debug",
+            "module": "superlinker::repr",
+            "message": "merge_into: using source symbol \"memcpy\"",
+            "symbol": "memcpy",
+            "provider": "source",
+        }));
+    }
+
+    #[test]
+    fn exit_codes() {
+        let image = || "image".to_owned();
+        let cases = [
+            (Error::Io { path: PathBuf::from("input"), error: std::io::ErrorKind::NotFound.into() }, 2),
+            (Error::MissingDependency { image: image(), dependency: "libc.so.6".to_owned() }, 2),
+            (Error::Parse { image: image(), error: elf::parse::ParseError::BadMagic([0; 4]) }, 3),
+            (Error::InvalidSavedImage { path: PathBuf::from("input"), reason: String::new() }, 3),
+            (Error::Malformed { image: image(), reason: String::new() }, 3),
+            (Error::InvalidRelocation { image: image(), offset: 0, reason: String::new() }, 3),
+            (Error::UnsupportedMachine { image: image(), machine: 0 }, 4),
+            (Error::UnsupportedFeature { image: image(), feature: String::new() }, 4),
+            (Error::UnsupportedRelocation { image: image(), offset: 0, r_type: 0 }, 4),
+            (Error::IncompatibleImages { source: image(), target: image(), reason: String::new() }, 5),
+            (Error::DuplicateSymbol { image: image(), symbol: String::new() }, 5),
+            (Error::SymbolConflict { source: image(), target: image(), symbol: String::new() }, 5),
+            (Error::InterpreterConflict { source: image(), target: image() }, 5),
+            (Error::MisalignedRebase { image: image(), offset: 0, alignment: 0 }, 5),
+            (Error::LocalBinding { image: image(), symbol: String::new(), offset: 0 }, 5),
+            (Error::CopyRelocation { image: image(), symbol: String::new(), offset: 0 }, 5),
+            (Error::Emit { image: image(), error: emit_error() }, 6),
+            (Error::Manifest { path: PathBuf::from("superlinker.toml"), reason: String::new() }, 7),
+        ];
+        for (error, code) in cases {
+            assert_eq!(exit_code(&error), code, "{:?}", error);
+        }
+    }
 }
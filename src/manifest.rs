@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::repr::*;
+use crate::error::Error;
+use crate::emit::{EmitOptions, HashStyle};
+use crate::resolve::SearchPaths;
+
+/// A description of a link, which can be kept in a TOML or JSON file instead of on the command line.
+///
+/// Relative paths in a manifest file are relative to the directory of the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// Where the output is written, unless it is given on the command line.
+    pub output: Option<PathBuf>,
+    /// The image that the sources are merged into.
+    pub target: PathBuf,
+    /// The images merged into the target, in order.
+    pub sources: Vec<PathBuf>,
+    /// The interpreter, which is merged last and so embedded into the output.
+    pub interpreter: Option<PathBuf>,
+    /// Whether the dependencies of the inputs are merged too, like with `--resolve`.
+    pub resolve: bool,
+    /// Like `--sysroot`.
+    pub sysroot: Option<PathBuf>,
+    /// Like `-L`, in order.
+    pub library_dirs: Vec<PathBuf>,
+    /// Name that becomes the first `DT_SONAME` of the output.
+    pub soname: Option<String>,
+    /// Like `--hash-style`.
+    pub hash_style: Option<HashStyle>,
+    /// Like `--noexecstack`.
+    pub noexecstack: bool,
+    /// Like `--allow-partial-relro`.
+    pub allow_partial_relro: bool,
+    /// What happens to symbols of the output.
+    pub symbols: SymbolPolicies,
+}
+
+/// What happens to symbols of the merged image, by name.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SymbolPolicies {
+    /// Names of defined symbols that are not exported from the output.
+    pub hide: Vec<String>,
+}
+
+impl Manifest {
+    /// Reads a manifest from a JSON file if its name ends with `.json`, and from a TOML file otherwise.
+    pub fn load(path: &Path) -> Result<Manifest, Error> {
+        let text = std::fs::read_to_string(path).map_err(|error| Error::Io { path: path.to_owned(), error })?;
+        let manifest_error = |reason: String| Error::Manifest { path: path.to_owned(), reason };
+        let mut manifest: Manifest = if path.extension().is_some_and(|extension| extension == "json") {
+            serde_json::from_str(&text).map_err(|error| manifest_error(error.to_string()))?
+        } else {
+            toml::from_str(&text).map_err(|error| manifest_error(error.to_string()))?
+        };
+        if manifest.target.as_os_str().is_empty() {
+            return Err(manifest_error("no target".to_owned()))
+        }
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let relative = |path: &mut PathBuf| *path = base_dir.join(&*path);
+        manifest.output.iter_mut()
+            .chain(std::iter::once(&mut manifest.target))
+            .chain(manifest.sources.iter_mut())
+            .chain(manifest.interpreter.iter_mut())
+            .chain(manifest.sysroot.iter_mut())
+            .chain(manifest.library_dirs.iter_mut())
+            .for_each(relative);
+        Ok(manifest)
+    }
+
+    /// Returns the paths of the images to merge, in order.
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        std::iter::once(&self.target)
+            .chain(self.sources.iter())
+            .chain(self.interpreter.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns where to look for dependencies, which is the same as with no options if the manifest has none.
+    pub fn search_paths(&self) -> SearchPaths {
+        let mut search_paths = SearchPaths::default();
+        if let Some(sysroot) = &self.sysroot {
+            search_paths.sysroot = sysroot.clone();
+        }
+        search_paths.library_dirs = self.library_dirs.clone();
+        search_paths
+    }
+
+    /// Returns the options for emitting the output, which are the defaults other than those in the manifest.
+    pub fn emit_options(&self) -> EmitOptions {
+        let mut emit_options = EmitOptions::default();
+        if let Some(hash_style) = self.hash_style {
+            emit_options.hash_style = hash_style;
+        }
+        emit_options.force_non_executable_stack = self.noexecstack;
+        emit_options.allow_partial_relro = self.allow_partial_relro;
+        emit_options
+    }
+
+    /// Applies the symbol policies and the soname to the merged image.
+    pub fn apply(&self, image: &mut Image) {
+        // Local symbols stay in the dynamic symbol table, where relocations can still refer to them, but the dynamic
+        // loader never binds references from other images to them.
+        for name in self.symbols.hide.iter() {
+            let mut found = false;
+            for symbol in image.symbols.iter_mut().filter(|symbol| symbol.name == *name) {
+                if matches!(symbol.scope, SymbolScope::Global | SymbolScope::Weak) && symbol.value != 0 {
+                    info!("manifest: hiding symbol {:?}", symbol.versioned_name());
+                    symbol.scope = SymbolScope::Local;
+                    found = true;
+                }
+            }
+            if !found {
+                warn!("manifest: cannot hide symbol {:?}, since it is not defined", name);
+            }
+        }
+        if let Some(soname) = &self.soname {
+            image.image_names.retain(|image_name| image_name != soname);
+            image.image_names.insert(0, soname.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_manifest(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("superlinker-{}-manifest", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn symbol(name: &str, scope: SymbolScope, value: u64) -> Symbol {
+        Symbol {
+            name: name.to_owned(),
+            version: None,
+            kind: SymbolKind::Code,
+            scope,
+            visibility: SymbolVisibility::Default,
+            value,
+            size: 0,
+            abs: false,
+        }
+    }
+
+    fn check_loaded(path: &Path) {
+        let manifest = Manifest::load(path).unwrap();
+        let dir = path.parent().unwrap();
+        assert_eq!(manifest.output, Some(dir.join("out/ls")));
+        assert_eq!(manifest.inputs(), [dir.join("ls"), PathBuf::from("/lib/libc.so.6"), dir.join("ld.so")]);
+        let search_paths = manifest.search_paths();
+        assert_eq!(search_paths.sysroot, dir.join("sysroot"));
+        assert_eq!(search_paths.library_dirs, [dir.join("lib")]);
+        let emit_options = manifest.emit_options();
+        assert_eq!(emit_options.hash_style, HashStyle::Gnu);
+        assert!(emit_options.force_non_executable_stack);
+        assert!(!emit_options.allow_partial_relro);
+        assert_eq!(manifest.soname.as_deref(), Some("ls"));
+        assert_eq!(manifest.symbols.hide, ["getfilecon"]);
+    }
+
+    // Paths are relative to the manifest, unless they are absolute.
+    #[test]
+    fn load_toml() {
+        check_loaded(&write_manifest("ls.toml", r#"
+            output = "out/ls"
+            target = "ls"
+            sources = ["/lib/libc.so.6"]
+            interpreter = "ld.so"
+            sysroot = "sysroot"
+            library_dirs = ["lib"]
+            soname = "ls"
+            hash_style = "gnu"
+            noexecstack = true
+
+            [symbols]
+            hide = ["getfilecon"]
+        "#));
+    }
+
+    #[test]
+    fn load_json() {
+        check_loaded(&write_manifest("ls.json", r#"{
+            "output": "out/ls",
+            "target": "ls",
+            "sources": ["/lib/libc.so.6"],
+            "interpreter": "ld.so",
+            "sysroot": "sysroot",
+            "library_dirs": ["lib"],
+            "soname": "ls",
+            "hash_style": "gnu",
+            "noexecstack": true,
+            "symbols": { "hide": ["getfilecon"] }
+        }"#));
+    }
+
+    #[test]
+    fn load_invalid() {
+        let cases = [
+            ("unknown.toml", "target = \"ls\"\nsoruces = []\n", "unknown field `soruces`"),
+            ("unknown_symbols.toml", "target = \"ls\"\n[symbols]\nexport = []\n", "unknown field `export`"),
+            ("unknown.json", r#"{ "target": "ls", "outptu": "ls" }"#, "unknown field `outptu`"),
+            ("no_target.toml", "sources = [\"libc.so.6\"]\n", "no target"),
+        ];
+        for (name, contents, expected_reason) in cases {
+            match Manifest::load(&write_manifest(name, contents)) {
+                Err(Error::Manifest { reason, .. }) =>
+                    assert!(reason.contains(expected_reason), "{}: unexpected reason {:?}", name, reason),
+                result => panic!("{}: unexpected result {:?}", name, result),
+            }
+        }
+    }
+
+    #[test]
+    fn apply() {
+        let manifest = Manifest {
+            soname: Some("libbar.so".to_owned()),
+            symbols: SymbolPolicies { hide: vec!["hidden".to_owned(), "imported".to_owned()] },
+            ..Manifest::default()
+        };
+        let mut image = Image {
+            machine: elf::abi::EM_X86_64,
+            byte_order: ByteOrder::Little,
+            word_size: WordSize::Bits64,
+            alignment: 0x1000,
+            segments: Vec::new(),
+            tls_image: None,
+            relro_ranges: Vec::new(),
+            stack_requirements: Vec::new(),
+            eh_frame_hdrs: Vec::new(),
+            notes: Vec::new(),
+            gnu_properties: Vec::new(),
+            symbols: vec![
+                symbol("visible", SymbolScope::Global, 0x1000),
+                symbol("hidden", SymbolScope::Global, 0x1010),
+                symbol("imported", SymbolScope::Import, 0),
+            ],
+            relocations: Vec::new(),
+            initializers: Vec::new(),
+            finalizers: Vec::new(),
+            dependencies: Vec::new(),
+            runpaths: Vec::new(),
+            image_names: vec!["libfoo.so.1".to_owned(), "libbar.so".to_owned()],
+            interpreter: Interpreter::Absent,
+            entry: 0,
+        };
+        manifest.apply(&mut image);
+        let scopes = image.symbols.iter().map(|symbol| symbol.scope).collect::<Vec<_>>();
+        assert_eq!(scopes, [SymbolScope::Global, SymbolScope::Local, SymbolScope::Import]);
+        assert_eq!(image.image_names, ["libbar.so", "libfoo.so.1"]);
+    }
+}
@@ -1,16 +1,47 @@
 use elf::abi::*;
 use elf::endian::EndianParse;
-use elf::relocation::RelaIterator;
+use elf::file::Class;
+use elf::gnu_symver::{SymbolVersionTable, VerDefIterator, VerNeedIterator, VersionIndexTable};
+use elf::parse::{ParseAt, ParseError};
+use elf::string_table::StringTable;
+use elf::symbol::SymbolTable;
+use elf::relocation::{RelIterator, RelaIterator};
 use elf::ElfBytes;
 
 use crate::repr::*;
+use crate::error::Error;
 
 pub const DT_RELR: i64 = 36;
 pub const DT_RELRSZ: i64 = 35;
 
+pub const R_386_32: u32 = 1;
+pub const R_386_COPY: u32 = 5;
+pub const R_386_GLOB_DAT: u32 = 6;
+pub const R_386_JMP_SLOT: u32 = 7;
+pub const R_386_RELATIVE: u32 = 8;
+pub const R_386_TLS_TPOFF: u32 = 14;
+pub const R_386_TLS_DTPMOD32: u32 = 35;
+pub const R_386_TLS_DTPOFF32: u32 = 36;
+pub const R_386_IRELATIVE: u32 = 42;
+
+fn parse_word_at<E: EndianParse>(parse: E, class: Class, offset: &mut usize, data: &[u8]) -> Result<u64, ParseError> {
+    match class {
+        Class::ELF32 => parse.parse_u32_at(offset, data).map(|word| word as u64),
+        Class::ELF64 => parse.parse_u64_at(offset, data),
+    }
+}
+
+fn parse_signed_word_at<E: EndianParse>(parse: E, class: Class, offset: &mut usize, data: &[u8])
+        -> Result<i64, ParseError> {
+    match class {
+        Class::ELF32 => parse.parse_i32_at(offset, data).map(|word| word as i64),
+        Class::ELF64 => parse.parse_i64_at(offset, data),
+    }
+}
+
 fn elf_vaddr_to_offset<E: EndianParse>(elf_data: &[u8], addr: u64) -> Result<usize, ()> {
-    let elf_file = ElfBytes::<E>::minimal_parse(elf_data).expect("Cannot parse");
-    let elf_segments = elf_file.segments().expect("No segments");
+    let elf_file = ElfBytes::<E>::minimal_parse(elf_data).map_err(|_| ())?;
+    let elf_segments = elf_file.segments().ok_or(())?;
     elf_segments
         .iter()
         .find(|segment| addr >= segment.p_vaddr && addr <= segment.p_vaddr + segment.p_memsz)
@@ -26,15 +57,108 @@ fn elf_vaddr_size_to_offset_range<E: EndianParse>(elf_data: &[u8], addr: u64, si
     }
 }
 
-pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Result<Image, elf::parse::ParseError> {
-    let elf_file = ElfBytes::<E>::minimal_parse(elf_data)?;
+// The number of dynamic symbols is the number of chain entries in the SysV hash table.
+fn elf_sysv_hash_symbol_count<E: EndianParse>(parse: E, elf_data: &[u8], addr: u64) -> Result<usize, ()> {
+    let mut offset = elf_vaddr_to_offset::<E>(elf_data, addr)? + /* nbucket */4;
+    parse.parse_u32_at(&mut offset, elf_data).map(|nchain| nchain as usize).map_err(|_| ())
+}
+
+// The GNU hash table does not record the number of symbols, but the hashed symbols come last, and each of them is
+// in the chain of its bucket. The last symbol is found by walking the chain of the last non-empty bucket to its end,
+// which is marked by the lowest bit of the hash.
+fn elf_gnu_hash_symbol_count<E: EndianParse>(parse: E, class: Class, elf_data: &[u8], addr: u64)
+        -> Result<usize, ()> {
+    let mut offset = elf_vaddr_to_offset::<E>(elf_data, addr)?;
+    let parse_u32 = |offset: &mut usize| parse.parse_u32_at(offset, elf_data).map_err(|_| ());
+    let bucket_count = parse_u32(&mut offset)?;
+    let symbol_base = parse_u32(&mut offset)?;
+    let bloom_count = parse_u32(&mut offset)?;
+    let _bloom_shift = parse_u32(&mut offset)?;
+    offset += bloom_count as usize * match class { Class::ELF32 => 4, Class::ELF64 => 8 };
+    let mut last_bucket_symbol = 0;
+    for _ in 0..bucket_count {
+        last_bucket_symbol = last_bucket_symbol.max(parse_u32(&mut offset)?);
+    }
+    if last_bucket_symbol < symbol_base {
+        return Ok(symbol_base as usize) // no hashed symbols
+    }
+    let chains_offset = offset;
+    let mut symbol_index = last_bucket_symbol;
+    loop {
+        let mut offset = chains_offset + (symbol_index - symbol_base) as usize * 4;
+        if parse_u32(&mut offset)? & 1 != 0 {
+            return Ok(symbol_index as usize + 1)
+        }
+        symbol_index += 1;
+    }
+}
+
+// A note segment is a sequence of entries, each of which consists of the name and descriptor sizes, the type, and
+// the name and the descriptor, both ending at the alignment of the segment (which is 4, except for notes that
+// contain 8-byte words, such as GNU property notes on 64-bit targets).
+type ElfNote<'data> = (/*name*/String, /*type*/u32, /*desc*/&'data [u8]);
+
+fn elf_notes<E: EndianParse>(parse: E, data: &[u8], align: usize) -> Result<Vec<ElfNote<'_>>, ParseError> {
+    let padded = |size: usize| (size + align - 1) & !(align - 1);
+    let mut notes = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let name_size = parse.parse_u32_at(&mut offset, data)? as usize;
+        let desc_size = parse.parse_u32_at(&mut offset, data)? as usize;
+        let note_type = parse.parse_u32_at(&mut offset, data)?;
+        let name_range = offset..offset + name_size;
+        offset = padded(offset + name_size);
+        let desc_range = offset..offset + desc_size;
+        offset = padded(offset + desc_size);
+        let (Some(name), Some(desc)) = (data.get(name_range.clone()), data.get(desc_range.clone())) else {
+            return Err(ParseError::SliceReadError((name_range.start, desc_range.end)))
+        };
+        let name = String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(name)).into_owned();
+        notes.push((name, note_type, desc));
+    }
+    Ok(notes)
+}
+
+// A GNU property note contains a sequence of properties, each of which consists of the type, the data size, and the
+// data, padded to the word size.
+fn elf_gnu_properties<E: EndianParse>(parse: E, class: Class, desc: &[u8]) -> Result<Vec<GnuProperty>, ParseError> {
+    let align = match class { Class::ELF32 => 4, Class::ELF64 => 8 };
+    let mut properties = Vec::new();
+    let mut offset = 0;
+    while offset < desc.len() {
+        let property_type = parse.parse_u32_at(&mut offset, desc)?;
+        let data_size = parse.parse_u32_at(&mut offset, desc)? as usize;
+        let data = desc.get(offset..offset + data_size)
+            .ok_or(ParseError::SliceReadError((offset, offset + data_size)))?;
+        offset += (data_size + align - 1) & !(align - 1);
+        properties.push(GnuProperty { property_type, data: data.to_owned() });
+    }
+    Ok(properties)
+}
+
+/// Lifts an ELF executable or shared library into an [`Image`].
+///
+/// The `soname` is used as the name of the image if it has no `DT_SONAME` of its own, and in diagnostics.
+pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Result<Image, Error> {
+    let image_name = soname.unwrap_or("<unnamed>");
+    let parse_error = |error: ParseError| Error::Parse { image: image_name.to_owned(), error };
+    let malformed = |reason: &str| Error::Malformed { image: image_name.to_owned(), reason: reason.to_owned() };
+    let unsupported = |feature: String| Error::UnsupportedFeature { image: image_name.to_owned(), feature };
+    let elf_file = ElfBytes::<E>::minimal_parse(elf_data).map_err(parse_error)?;
     let machine = elf_file.ehdr.e_machine;
-    let elf_common = elf_file.find_common_data()?;
-    let elf_segments = elf_file.segments().expect("No segments");
+    let class = elf_file.ehdr.class;
+    let word_size = match class {
+        Class::ELF32 => WordSize::Bits32,
+        Class::ELF64 => WordSize::Bits64,
+    };
+    let parse = E::from_ei_data(elf_data[EI_DATA]).map_err(parse_error)?;
+    let byte_order = if parse.is_little() { ByteOrder::Little } else { ByteOrder::Big };
+    let elf_common = elf_file.find_common_data().map_err(parse_error)?;
+    let elf_segments = elf_file.segments().ok_or_else(|| malformed("no program headers"))?;
     let alignment = elf_segments
         .iter()
         .filter_map(|elf_segment| {
-            if elf_segment.p_type == PT_LOAD { Some(elf_segment.p_align) } else { None }
+            if elf_segment.p_type == PT_LOAD { Some(elf_segment.p_align.max(1)) } else { None }
         })
         .max()
         .unwrap_or(1);
@@ -44,9 +168,10 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
             if elf_segment.p_type == PT_LOAD {
                 let addr = elf_segment.p_vaddr;
                 let size = elf_segment.p_memsz;
-                let data = elf_file.segment_data(&elf_segment)
-                    .expect("No data for PT_LOAD")
-                    .to_owned();
+                let data = match elf_file.segment_data(&elf_segment) {
+                    Ok(data) => data.to_owned(),
+                    Err(error) => return Some(Err(parse_error(error))),
+                };
                 let mode = if elf_segment.p_flags == PF_R {
                     LoadMode::ReadOnly
                 } else if elf_segment.p_flags == PF_R | PF_W {
@@ -54,51 +179,239 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
                 } else if elf_segment.p_flags == PF_R | PF_X {
                     LoadMode::ReadExecute
                 } else {
-                    panic!("Unknown segment flags: {}",
-                        elf::to_str::p_flags_to_string(elf_segment.p_flags))
+                    return Some(Err(unsupported(format!("segment flags {}",
+                        elf::to_str::p_flags_to_string(elf_segment.p_flags)))))
                 };
-                Some(LoadSegment { addr, size, data, mode })
+                Some(Ok(LoadSegment { addr, size, data, mode }))
             } else {
                 None
             }
 
         })
+        .collect::<Result<Vec<_>, _>>()?;
+    let relro_ranges = elf_segments
+        .iter()
+        .filter_map(|elf_segment| {
+            if elf_segment.p_type == PT_GNU_RELRO {
+                Some(ImageRange {
+                    image_name: image_name.to_owned(),
+                    addr: elf_segment.p_vaddr,
+                    size: elf_segment.p_memsz
+                })
+            } else {
+                None
+            }
+        })
         .collect::<Vec<_>>();
+    let eh_frame_hdrs = elf_segments
+        .iter()
+        .filter_map(|elf_segment| {
+            if elf_segment.p_type == PT_GNU_EH_FRAME {
+                Some(ImageRange {
+                    image_name: image_name.to_owned(),
+                    addr: elf_segment.p_vaddr,
+                    size: elf_segment.p_memsz
+                })
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    // GNU property notes are kept apart from the other notes, since they have to be combined rather than collected
+    // when merging images. (Before PT_GNU_PROPERTY was introduced, they were only found among the other notes.)
+    let mut notes = Vec::new();
+    let mut gnu_properties = Vec::new();
+    for elf_segment in elf_segments.iter().filter(|elf_segment| elf_segment.p_type == PT_NOTE) {
+        let data = elf_file.segment_data(&elf_segment).map_err(parse_error)?;
+        let align = if elf_segment.p_align == 8 { 8 } else { 4 };
+        for (name, note_type, desc) in elf_notes(parse, data, align).map_err(parse_error)? {
+            if name == "GNU" && note_type as u64 == NT_GNU_PROPERTY_TYPE_0 {
+                gnu_properties.extend(elf_gnu_properties(parse, class, desc).map_err(parse_error)?);
+            } else {
+                notes.push(Note { image_name: image_name.to_owned(), name, note_type, desc: desc.to_owned() });
+            }
+        }
+    }
+    gnu_properties.sort_by_key(|property| property.property_type);
+    gnu_properties.dedup_by_key(|property| property.property_type);
+    // Without PT_GNU_STACK, the dynamic loader assumes that the stack must be executable on x86, where it used to be
+    // executable by default; on newer machines, such as AArch64 and RISC-V, the default is a non-executable stack.
+    let executable_by_default = matches!(machine, EM_386 | EM_X86_64);
+    let stack_requirement = elf_segments
+        .iter()
+        .find(|elf_segment| elf_segment.p_type == PT_GNU_STACK)
+        .map(|elf_segment| StackRequirement {
+            image_name: image_name.to_owned(),
+            executable: elf_segment.p_flags & PF_X != 0,
+            size: elf_segment.p_memsz,
+        })
+        .unwrap_or_else(|| StackRequirement {
+            image_name: image_name.to_owned(),
+            executable: executable_by_default,
+            size: 0
+        });
     let tls_image = elf_segments
         .iter()
         .find_map(|elf_segment| {
             if elf_segment.p_type == PT_TLS {
                 let mut tls_image = vec![0; elf_segment.p_memsz as usize];
-                let data = elf_file.segment_data(&elf_segment)
-                    .expect("No data for PT_TLS")
-                    .to_owned();
-                tls_image[..data.len()].copy_from_slice(&data[..]);
-                Some(tls_image)
+                let data = match elf_file.segment_data(&elf_segment) {
+                    Ok(data) => data,
+                    Err(error) => return Some(Err(parse_error(error))),
+                };
+                if data.len() > tls_image.len() {
+                    return Some(Err(malformed("PT_TLS file size exceeds its memory size")))
+                }
+                tls_image[..data.len()].copy_from_slice(data);
+                Some(Ok(TlsImage { data: tls_image, alignment: elf_segment.p_align.max(1) }))
             } else {
                 None
             }
-        });
-    let elf_dynsyms = elf_common.dynsyms.as_ref().expect("No dynamic symbol table");
-    let elf_dynsyms_strs = elf_common.dynsyms_strs.as_ref().expect("No dynamic symbol string table");
+        })
+        .transpose()?;
+    let elf_dynamic = elf_common.dynamic.map(|elf_dynamic| {
+        elf_dynamic.into_iter().collect::<Vec<_>>()
+    }).unwrap_or(Vec::new());
+    // Without section headers (e.g. after `sstrip`), the dynamic symbol table is found via the dynamic table, like
+    // the dynamic linker does it. The size of the symbol table is not recorded anywhere, but all of the symbols are
+    // in the hash table, so it can be used to determine the number of symbols. Either kind of hash table will do.
+    let (elf_dynsyms, elf_dynsyms_strs) = match (elf_common.dynsyms, elf_common.dynsyms_strs) {
+        (Some(elf_dynsyms), Some(elf_dynsyms_strs)) => (elf_dynsyms, elf_dynsyms_strs),
+        _ => {
+            let find_dynamic = |d_tag| elf_dynamic.iter().find_map(|elf_dyn| {
+                if elf_dyn.d_tag == d_tag { Some(elf_dyn.clone().d_val()) } else { None }
+            });
+            let elf_dynamic_symtab = find_dynamic(DT_SYMTAB)
+                .ok_or_else(|| malformed("no dynamic symbol table"))?;
+            let elf_dynamic_strtab = find_dynamic(DT_STRTAB)
+                .ok_or_else(|| malformed("no dynamic symbol string table"))?;
+            let elf_dynamic_strsz = find_dynamic(DT_STRSZ)
+                .ok_or_else(|| malformed("expected dynamic table to have DT_STRSZ with DT_STRTAB"))?;
+            let elf_dynsyms_count = match (find_dynamic(DT_HASH), find_dynamic(DT_GNU_HASH)) {
+                (Some(elf_dynamic_hash), _) =>
+                    elf_sysv_hash_symbol_count::<E>(parse, elf_data, elf_dynamic_hash),
+                (None, Some(elf_dynamic_gnu_hash)) =>
+                    elf_gnu_hash_symbol_count::<E>(parse, class, elf_data, elf_dynamic_gnu_hash),
+                (None, None) =>
+                    return Err(malformed("no hash table to determine the size of the dynamic symbol table")),
+            }.map_err(|_| malformed("hash table out of bounds"))?;
+            let elf_dynsyms_size = (elf_dynsyms_count * elf::symbol::Symbol::size_for(class)) as u64;
+            let elf_dynsyms_range =
+                elf_vaddr_size_to_offset_range::<E>(elf_data, elf_dynamic_symtab, elf_dynsyms_size)
+                .map_err(|_| malformed("dynamic symbol table out of bounds"))?;
+            let elf_dynsyms_strs_range =
+                elf_vaddr_size_to_offset_range::<E>(elf_data, elf_dynamic_strtab, elf_dynamic_strsz)
+                .map_err(|_| malformed("dynamic symbol string table out of bounds"))?;
+            (SymbolTable::new(parse, class, &elf_data[elf_dynsyms_range]),
+                StringTable::new(&elf_data[elf_dynsyms_strs_range]))
+        }
+    };
+    // The symbol version tables are found via the dynamic table, the same as all other dynamic linking information.
+    let elf_dynamic_versym = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_VERSYM { Some(elf_dyn.clone().d_val()) } else { None }
+    });
+    let elf_dynamic_verneed = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_VERNEED { Some(elf_dyn.clone().d_val()) } else { None }
+    });
+    let elf_dynamic_verneednum = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_VERNEEDNUM { Some(elf_dyn.clone().d_val()) } else { None }
+    });
+    let elf_dynamic_verdef = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_VERDEF { Some(elf_dyn.clone().d_val()) } else { None }
+    });
+    let elf_dynamic_verdefnum = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_VERDEFNUM { Some(elf_dyn.clone().d_val()) } else { None }
+    });
+    let elf_data_from = |addr| {
+        elf_vaddr_to_offset::<E>(elf_data, addr).ok()
+            .and_then(|offset| elf_data.get(offset..))
+    };
+    let elf_symbol_versions = match elf_dynamic_versym {
+        Some(elf_dynamic_versym) => {
+            let versym_range =
+                elf_vaddr_size_to_offset_range::<E>(elf_data, elf_dynamic_versym, 2 * elf_dynsyms.len() as u64)
+                .map_err(|_| malformed("versym data out of bounds"))?;
+            let elf_version_indices = VersionIndexTable::new(parse, class, &elf_data[versym_range.clone()]);
+            let elf_verneeds = match (elf_dynamic_verneed, elf_dynamic_verneednum) {
+                (Some(elf_dynamic_verneed), Some(elf_dynamic_verneednum)) => {
+                    let elf_verneed_data = elf_data_from(elf_dynamic_verneed)
+                        .ok_or_else(|| malformed("verneed data out of bounds"))?;
+                    Some((VerNeedIterator::new(parse, class, elf_dynamic_verneednum, 0, elf_verneed_data),
+                        elf_dynsyms_strs))
+                }
+                (None, None) => None,
+                _ => return Err(malformed("expected dynamic table to have both or neither of DT_VERNEED and DT_VERNEEDNUM"))
+            };
+            let elf_verdefs = match (elf_dynamic_verdef, elf_dynamic_verdefnum) {
+                (Some(elf_dynamic_verdef), Some(elf_dynamic_verdefnum)) => {
+                    let elf_verdef_data = elf_data_from(elf_dynamic_verdef)
+                        .ok_or_else(|| malformed("verdef data out of bounds"))?;
+                    Some((VerDefIterator::new(parse, class, elf_dynamic_verdefnum, 0, elf_verdef_data),
+                        elf_dynsyms_strs))
+                }
+                (None, None) => None,
+                _ => return Err(malformed("expected dynamic table to have both or neither of DT_VERDEF and DT_VERDEFNUM"))
+            };
+            let elf_symbol_version_table = SymbolVersionTable::new(
+                VersionIndexTable::new(parse, class, &elf_data[versym_range]), elf_verneeds, elf_verdefs);
+            Some((elf_version_indices, elf_symbol_version_table))
+        }
+        None => None
+    };
+    // Version indices are shared between definitions and requirements. Usually, defined symbols refer to
+    // definitions and undefined symbols refer to requirements, but the target of a copy relocation is defined
+    // in the executable with the version it requires from the library.
+    let symbol_version = |elf_symbol_index: usize| {
+        let (elf_version_indices, elf_symbol_version_table) = match elf_symbol_versions {
+            Some(ref elf_symbol_versions) => elf_symbol_versions,
+            None => return Ok(None)
+        };
+        // Version indices 0 and 1 are used for local and global unversioned symbols.
+        if elf_version_indices.get(elf_symbol_index).map_err(parse_error)?.index() <= 1 {
+            return Ok(None)
+        }
+        if let Some(mut definition) = elf_symbol_version_table.get_definition(elf_symbol_index)
+                .map_err(parse_error)? {
+            let name = definition.names.next()
+                .ok_or_else(|| malformed("version definition has no name"))?
+                .map_err(parse_error)?;
+            Ok(Some(SymbolVersion { name: name.to_owned(), hidden: definition.hidden, file: None }))
+        } else if let Some(requirement) = elf_symbol_version_table.get_requirement(elf_symbol_index)
+                .map_err(parse_error)? {
+            Ok(Some(SymbolVersion {
+                name: requirement.name.to_owned(),
+                hidden: requirement.hidden,
+                file: Some(requirement.file.to_owned()),
+            }))
+        } else {
+            Err(malformed("symbol refers to a missing version"))
+        }
+    };
     let symbols = elf_dynsyms
         .clone()
         .into_iter()
+        .enumerate()
         .skip(1)
-        .filter_map(|elf_symbol| {
+        .filter_map(|(elf_symbol_index, elf_symbol)| {
             // The type of the symbol can be `STT_NOTYPE` if it is a reference to a symbol that the static linker could
             // not discover at link time. This is independent of how the symbol was declared in C, i.e. `extern int a;`,
             // `extern int a(void);`, and `extern double a;` all become `STT_NOTYPE` when the symbol isn't resolved.
             // Weak symbols generally end up as `STT_NOTYPE`, unless defined in the same object.
             let elf_symtype = elf_symbol.st_symtype();
-            if elf_symtype == STT_FUNC || elf_symtype == STT_OBJECT || elf_symtype == STT_NOTYPE {
-                let name = elf_dynsyms_strs
-                    .get(elf_symbol.st_name as usize)
-                    .expect("Invalid symbol name")
-                    .to_owned();
+            if elf_symtype == STT_FUNC || elf_symtype == STT_GNU_IFUNC || elf_symtype == STT_OBJECT ||
+                    elf_symtype == STT_TLS || elf_symtype == STT_NOTYPE {
+                let name = match elf_dynsyms_strs.get(elf_symbol.st_name as usize) {
+                    Ok(name) => name.to_owned(),
+                    Err(error) => return Some(Err(parse_error(error))),
+                };
                 let kind = if elf_symtype == STT_FUNC {
                     SymbolKind::Code
+                } else if elf_symtype == STT_GNU_IFUNC {
+                    SymbolKind::Ifunc
                 } else if elf_symtype == STT_OBJECT {
                     SymbolKind::Data
+                } else if elf_symtype == STT_TLS {
+                    SymbolKind::Tls
                 } else {
                     SymbolKind::Unknown
                 };
@@ -114,77 +427,146 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
                 } else if elf_symbol.st_bind() == STB_LOCAL {
                         SymbolScope::Local
                 } else {
-                    panic!("Unhandled symbol visibility: {}",
-                        elf::to_str::st_bind_to_str(elf_symbol.st_bind()).unwrap_or("<unknown>"))
+                    return Some(Err(unsupported(format!("symbol binding {} of {:?}",
+                        elf::to_str::st_bind_to_str(elf_symbol.st_bind()).unwrap_or("<unknown>"), name))))
                 };
                 if elf_symbol.st_shndx == SHN_COMMON {
-                    panic!("Unhandled special shndx {:#x}", elf_symbol.st_shndx);
+                    return Some(Err(unsupported(format!("special section index {:#x} of {:?}",
+                        elf_symbol.st_shndx, name))))
                 }
+                let version = match symbol_version(elf_symbol_index) {
+                    Ok(version) => version,
+                    Err(error) => return Some(Err(error)),
+                };
+                let visibility = match elf_symbol.st_vis() {
+                    STV_DEFAULT => SymbolVisibility::Default,
+                    STV_PROTECTED => SymbolVisibility::Protected,
+                    STV_HIDDEN => SymbolVisibility::Hidden,
+                    _ => SymbolVisibility::Internal,
+                };
                 let size = elf_symbol.st_size;
-                Some(Symbol { name, kind, scope, value, size, abs: (elf_symbol.st_shndx == SHN_ABS) })
-            } else if elf_symtype == STT_TLS {
-                panic!("Unhangled STT_TLS symbol");
+                Some(Ok(Symbol { name, version, kind, scope, visibility, value, size,
+                    abs: (elf_symbol.st_shndx == SHN_ABS) }))
             } else {
                 None
             }
         })
+        .collect::<Result<Vec<_>, _>>()?;
+    let lift_relocation = |r_offset: u64, r_sym: u32, r_type: u32, r_addend: i64| -> Result<Relocation, Error> {
+        let invalid = |reason: &str| Error::InvalidRelocation {
+            image: image_name.to_owned(),
+            offset: r_offset,
+            reason: reason.to_owned()
+        };
+        let symbol = if r_sym == 0 { None } else {
+            let elf_symbol = elf_dynsyms
+                .get(r_sym as usize)
+                .map_err(|_| invalid("symbol index out of bounds"))?;
+            let elf_symbol_name = elf_dynsyms_strs
+                .get(elf_symbol.st_name as usize)
+                .map_err(|_| invalid("symbol name out of bounds"))?;
+            let version = symbol_version(r_sym as usize)?;
+            Some(versioned_symbol_name(elf_symbol_name, version.as_ref()))
+        };
+        let require_symbol = |symbol: Option<String>|
+            symbol.ok_or_else(|| invalid("relocation type requires a symbol"));
+        let target = match (elf_file.ehdr.e_machine, r_type) {
+            // Both `*_GLOB_DAT` and `*_JUMP_SLOT` relocations can be expressed in terms of the more general
+            // and less optimized absolute relocation (e.g. `R_X86_64_64`), which is what the emitter is using.
+            (EM_X86_64, R_X86_64_64 | R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT) |
+            (EM_AARCH64, R_AARCH64_ABS64 | R_AARCH64_GLOB_DAT | R_AARCH64_JUMP_SLOT) |
+            (EM_RISCV, R_RISCV_64 | R_RISCV_JUMP_SLOT) |
+            (EM_386, R_386_32 | R_386_GLOB_DAT | R_386_JMP_SLOT) => {
+                RelocationTarget::Symbol {
+                    symbol: require_symbol(symbol)?,
+                    addend: r_addend
+                }
+            }
+            (EM_X86_64, R_X86_64_RELATIVE) |
+            (EM_AARCH64, R_AARCH64_RELATIVE) |
+            (EM_RISCV, R_RISCV_RELATIVE) |
+            (EM_386, R_386_RELATIVE) => {
+                if r_sym != 0 { return Err(invalid("relative relocation accepts no symbol")) }
+                RelocationTarget::Base { addend: r_addend }
+            }
+            (EM_X86_64, R_X86_64_IRELATIVE) |
+            (EM_AARCH64, R_AARCH64_IRELATIVE) |
+            (EM_RISCV, R_RISCV_IRELATIVE) |
+            (EM_386, R_386_IRELATIVE) => {
+                if r_sym != 0 { return Err(invalid("indirect relocation accepts no symbol")) }
+                RelocationTarget::IRelative { resolver: r_addend as u64 }
+            }
+            (EM_X86_64, R_X86_64_COPY) |
+            (EM_AARCH64, R_AARCH64_COPY) |
+            (EM_RISCV, R_RISCV_COPY) |
+            (EM_386, R_386_COPY) => {
+                RelocationTarget::Copy {
+                    symbol: require_symbol(symbol)?,
+                }
+            }
+            (EM_X86_64, R_X86_64_DTPMOD64) |
+            (EM_AARCH64, R_AARCH64_TLS_DTPMOD) |
+            (EM_RISCV, R_RISCV_TLS_DTPMOD64) |
+            (EM_386, R_386_TLS_DTPMOD32) => {
+                if r_addend != 0 { return Err(invalid("TLS module relocation accepts no addend")) }
+                RelocationTarget::TlsModule { symbol }
+            }
+            (EM_X86_64, R_X86_64_DTPOFF64) |
+            (EM_AARCH64, R_AARCH64_TLS_DTPREL) |
+            (EM_RISCV, R_RISCV_TLS_DTPREL64) |
+            (EM_386, R_386_TLS_DTPOFF32) => {
+                RelocationTarget::TlsOffset { symbol, addend: r_addend }
+            }
+            (EM_X86_64, R_X86_64_TPOFF64) |
+            (EM_AARCH64, R_AARCH64_TLS_TPREL) |
+            (EM_RISCV, R_RISCV_TLS_TPREL64) |
+            (EM_386, R_386_TLS_TPOFF) => {
+                RelocationTarget::ThreadPointerOffset { symbol, addend: r_addend }
+            }
+            (EM_X86_64 | EM_AARCH64 | EM_RISCV | EM_386, r_type) =>
+                return Err(Error::UnsupportedRelocation { image: image_name.to_owned(), offset: r_offset, r_type }),
+            (e_machine, _) =>
+                return Err(Error::UnsupportedMachine { image: image_name.to_owned(), machine: e_machine })
+        };
+        Ok(Relocation { offset: r_offset, target })
+    };
+    // "Rel" relocations keep their addend in the relocated location (and not in the relocation table) in the cases
+    // where an addend is used at all. The location has to be backed by the file, since the zero-filled part of
+    // a segment (bss) cannot hold an addend.
+    let elf_load_segments = elf_segments.iter()
+        .filter(|elf_segment| elf_segment.p_type == PT_LOAD)
         .collect::<Vec<_>>();
-    let elf_dynamic = elf_common.dynamic.map(|elf_dynamic| {
-        elf_dynamic.into_iter().collect::<Vec<_>>()
-    }).unwrap_or(Vec::new());
+    let parse_implicit_addend = |addr: u64| -> Result<i64, Error> {
+        let out_of_bounds = || Error::InvalidRelocation {
+            image: image_name.to_owned(),
+            offset: addr,
+            reason: "relocated location out of bounds".to_owned()
+        };
+        let end = addr.checked_add(word_size.bytes()).ok_or_else(out_of_bounds)?;
+        let elf_segment = elf_load_segments.iter()
+            .find(|elf_segment| addr >= elf_segment.p_vaddr && end <= elf_segment.p_vaddr + elf_segment.p_filesz)
+            .ok_or_else(out_of_bounds)?;
+        let mut file_offset = (elf_segment.p_offset + (addr - elf_segment.p_vaddr)) as usize;
+        parse_signed_word_at(parse, class, &mut file_offset, elf_data)
+            .map_err(|_| out_of_bounds())
+    };
     let parse_elf_rela = |elf_rela_data| {
-        RelaIterator::new(elf_file.ehdr.endianness, elf_file.ehdr.class, elf_rela_data)
-            .map(|elf_rela| {
-                let offset = elf_rela.r_offset;
-                let target = if elf_file.ehdr.e_machine == EM_X86_64 {
-                    let symbol = if elf_rela.r_sym == 0 { None } else {
-                        let elf_symbol = elf_dynsyms
-                            .get(elf_rela.r_sym as usize)
-                            .expect("Invalid symbol reference in relocation");
-                        let elf_symbol_name = elf_dynsyms_strs
-                            .get(elf_symbol.st_name as usize)
-                            .expect("Invalid symbol name in relocation");
-                        Some(elf_symbol_name.to_owned())
-                    };
-                    // Both `R_X86_64_GLOB_DAT` and `R_X86_64_JUMP_SLOT` relocations can be expressed in terms of
-                    // the more general and less optimized `R_X86_64_64` relocation, which is what the emitter is using.
-                    if elf_rela.r_type == R_X86_64_64 {
-                        RelocationTarget::Symbol {
-                            symbol: symbol.expect("R_X86_64_64 requires a symbol"),
-                            addend: elf_rela.r_addend
-                        }
-                    } else if elf_rela.r_type == R_X86_64_GLOB_DAT {
-                        RelocationTarget::Symbol {
-                            symbol: symbol.expect("R_X86_64_GLOB_DAT requires a symbol"),
-                            addend: elf_rela.r_addend
-                        }
-                    } else if elf_rela.r_type == R_X86_64_JUMP_SLOT {
-                        RelocationTarget::Symbol {
-                            symbol: symbol.expect("R_X86_64_JUMP_SLOT requires a symbol"),
-                            addend: elf_rela.r_addend
-                        }
-                    } else if elf_rela.r_type == R_X86_64_RELATIVE {
-                        assert!(elf_rela.r_sym == 0, "R_X86_64_RELATIVE accepts no symbol");
-                        RelocationTarget::Base { addend: elf_rela.r_addend }
-                    } else if elf_rela.r_type == R_X86_64_COPY {
-                        RelocationTarget::Copy {
-                            symbol: symbol.expect("R_X86_64_COPY requires a symbol"),
-                        }
-                    } else if [R_X86_64_DTPMOD64].contains(&elf_rela.r_type) {
-                        assert!(elf_rela.r_sym == 0, "Generic relocation mechanism accepts no symbol");
-                        assert!(elf_rela.r_addend == 0, "Generic relocation mechanism accepts no addend");
-                        RelocationTarget::ElfSpecific(elf_rela.r_type)
-                    } else {
-                        panic!("Unhandled relocation type: {}", elf_rela.r_type)
-                    }
-                } else {
-                    panic!("Unhandled machine for RELA relocations: {}",
-                        elf::to_str::e_machine_to_str(elf_file.ehdr.e_machine)
-                        .unwrap_or("<unknown>"))
+        RelaIterator::new(elf_file.ehdr.endianness, class, elf_rela_data)
+            .map(|elf_rela| lift_relocation(elf_rela.r_offset, elf_rela.r_sym, elf_rela.r_type, elf_rela.r_addend))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let parse_elf_rel = |elf_rel_data| {
+        RelIterator::new(elf_file.ehdr.endianness, class, elf_rel_data)
+            .map(|elf_rel| -> Result<Relocation, Error> {
+                let r_addend = match (elf_file.ehdr.e_machine, elf_rel.r_type) {
+                    // These relocations compute `S` only, and the relocated location holds e.g. the address of
+                    // the lazy binding trampoline instead of an addend.
+                    (EM_386, R_386_GLOB_DAT | R_386_JMP_SLOT | R_386_COPY | R_386_TLS_DTPMOD32) => 0,
+                    _ => parse_implicit_addend(elf_rel.r_offset)?,
                 };
-                Relocation { offset, target }
+                lift_relocation(elf_rel.r_offset, elf_rel.r_sym, elf_rel.r_type, r_addend)
             })
-            .collect::<Vec<_>>()
+            .collect::<Result<Vec<_>, _>>()
     };
     let elf_dynamic_rela = elf_dynamic.iter().find_map(|elf_dyn| {
         if elf_dyn.d_tag == DT_RELA { Some(elf_dyn.clone().d_val()) } else { None }
@@ -192,6 +574,12 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
     let elf_dynamic_relasz = elf_dynamic.iter().find_map(|elf_dyn| {
         if elf_dyn.d_tag == DT_RELASZ { Some(elf_dyn.clone().d_val()) } else { None }
     });
+    let elf_dynamic_rel = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_REL { Some(elf_dyn.clone().d_val()) } else { None }
+    });
+    let elf_dynamic_relsz = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_RELSZ { Some(elf_dyn.clone().d_val()) } else { None }
+    });
     let elf_dynamic_pltrel = elf_dynamic.iter().find_map(|elf_dyn| {
         if elf_dyn.d_tag == DT_PLTREL { Some(elf_dyn.clone().d_val() as i64) } else { None }
     });
@@ -211,70 +599,76 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
         (Some(elf_dynamic_rela), Some(elf_dynamic_relasz)) => {
             let rela_range =
                 elf_vaddr_size_to_offset_range::<E>(elf_data, elf_dynamic_rela, elf_dynamic_relasz)
-                .expect("Rela data out of bounds");
-            parse_elf_rela(&elf_data[rela_range])
+                .map_err(|_| malformed("rela data out of bounds"))?;
+            parse_elf_rela(&elf_data[rela_range])?
         }
         (None, None) => Vec::new(),
-        _ => panic!("Expected dynamic table to have both or neither of PT_RELA and PT_RELASZ")
+        _ => return Err(malformed("expected dynamic table to have both or neither of DT_RELA and DT_RELASZ"))
+    };
+    match (elf_dynamic_rel, elf_dynamic_relsz) {
+        (Some(elf_dynamic_rel), Some(elf_dynamic_relsz)) => {
+            let rel_range =
+                elf_vaddr_size_to_offset_range::<E>(elf_data, elf_dynamic_rel, elf_dynamic_relsz)
+                .map_err(|_| malformed("rel data out of bounds"))?;
+            data_relocations.append(&mut parse_elf_rel(&elf_data[rel_range])?)
+        }
+        (None, None) => (),
+        _ => return Err(malformed("expected dynamic table to have both or neither of DT_REL and DT_RELSZ"))
     };
     let mut code_relocations = match (elf_dynamic_pltrel, elf_dynamic_jmprel, elf_dynamic_pltrelsz) {
         (Some(elf_dynamic_pltrel), Some(elf_dynamic_jmprel), Some(elf_dynamic_pltrelsz)) => {
             let jmprel_range =
                 elf_vaddr_size_to_offset_range::<E>(elf_data, elf_dynamic_jmprel, elf_dynamic_pltrelsz)
-                .expect("Jmprel data out of bounds");
+                .map_err(|_| malformed("jmprel data out of bounds"))?;
             if elf_dynamic_pltrel == DT_RELA {
-                parse_elf_rela(&elf_data[jmprel_range])
-            // } else if elf_dynamic_pltrel == DT_REL {
-            //     parse_elf_rel(elf_pltrel_data)
+                parse_elf_rela(&elf_data[jmprel_range])?
+            } else if elf_dynamic_pltrel == DT_REL {
+                parse_elf_rel(&elf_data[jmprel_range])?
             } else {
-                panic!("Unhandled PLT relocation type: {}",
-                    elf::to_str::d_tag_to_str(elf_dynamic_pltrel)
-                    .unwrap_or("<unknown>"));
+                return Err(malformed("DT_PLTREL is neither DT_RELA nor DT_REL"))
             }
         }
         (None, None, None) => Vec::new(),
-        _ => panic!("Expected dynamic table to have all or none of PT_PLTREL, PT_JMPREL, and PT_PLTRELSZ")
+        _ => return Err(malformed("expected dynamic table to have all or none of DT_PLTREL, DT_JMPREL, and DT_PLTRELSZ"))
     };
     let mut relr_relocations = Vec::new();
     match (elf_dynamic_relr, elf_dynamic_relrsz) {
         (Some(elf_dynamic_relr), Some(elf_dynamic_relrsz)) => {
             let relr_range =
                 elf_vaddr_size_to_offset_range::<E>(elf_data, elf_dynamic_relr, elf_dynamic_relrsz)
-                .expect("Relr data out of bounds");
+                .map_err(|_| malformed("relr data out of bounds"))?;
             let elf_relr_data = &elf_data[relr_range];
-            let parse = E::from_ei_data(elf_data[EI_DATA]).unwrap();
-            let get_addend = |addr| {
-                let mut file_offset = elf_vaddr_to_offset::<E>(elf_data, addr)
-                    .expect("Relr target out of bounds");
-                parse.parse_i64_at(&mut file_offset, elf_data).unwrap()
-            };
-            let mut push_relr = |addr|
+            let mut push_relr = |addr| -> Result<(), Error> {
                 relr_relocations.push(Relocation {
                     offset: addr,
-                    target: RelocationTarget::Base { addend: get_addend(addr) }
+                    target: RelocationTarget::Base { addend: parse_implicit_addend(addr)? }
                 });
+                Ok(())
+            };
+            let word_bytes = word_size.bytes();
             let mut offset = 0;
             let mut next_rel = 0;
             while offset < elf_relr_data.len() {
-                let mut entry = parse.parse_u64_at(&mut offset, elf_relr_data).unwrap();
+                let mut entry = parse_word_at(parse, class, &mut offset, elf_relr_data)
+                    .map_err(|_| malformed("truncated relr data"))?;
                 if (entry & 1) == 0 {
-                    push_relr(entry as u64);
-                    next_rel = entry + 8;
+                    push_relr(entry)?;
+                    next_rel = entry + word_bytes;
                 } else {
                     let mut iter_rel = next_rel;
                     while (entry & !1) != 0 {
                         entry >>= 1;
                         if entry & 1 == 1 {
-                            push_relr(iter_rel as u64);
+                            push_relr(iter_rel)?;
                         }
-                        iter_rel += 8;
+                        iter_rel += word_bytes;
                     }
-                    next_rel = next_rel + 8 * 63;
+                    next_rel += word_bytes * (word_bytes * 8 - 1);
                 }
             }
         }
         (None, None) => (),
-        _ => panic!("Expected dynamic table to have all or none of DT_RELR and DT_RELRSZ")
+        _ => return Err(malformed("expected dynamic table to have both or neither of DT_RELR and DT_RELRSZ"))
     };
     let mut relocations = Vec::new();
     relocations.append(&mut relr_relocations); // ABI suggests processing Relr first
@@ -284,12 +678,28 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
         if elf_dyn.d_tag == DT_NEEDED {
             Some(elf_dynsyms_strs
                 .get(elf_dyn.clone().d_val() as usize)
-                .expect("Invalid DT_NEEDED name")
-                .to_owned())
+                .map(|name| name.to_owned())
+                .map_err(parse_error))
         } else {
             None
         }
-    }).collect::<Vec<_>>();
+    }).collect::<Result<Vec<_>, _>>()?;
+    // DT_RPATH is only consulted by the dynamic loader if DT_RUNPATH is absent.
+    let elf_dynamic_runpath = elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_RUNPATH { Some(elf_dyn.clone().d_val()) } else { None }
+    }).or_else(|| elf_dynamic.iter().find_map(|elf_dyn| {
+        if elf_dyn.d_tag == DT_RPATH { Some(elf_dyn.clone().d_val()) } else { None }
+    }));
+    let runpaths = match elf_dynamic_runpath {
+        Some(elf_dynamic_runpath) => elf_dynsyms_strs
+            .get(elf_dynamic_runpath as usize)
+            .map_err(parse_error)?
+            .split(':')
+            .filter(|path| !path.is_empty())
+            .map(|path| path.to_owned())
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
     let elf_dynamic_init = elf_dynamic.iter().find_map(|elf_dyn| {
         if elf_dyn.d_tag == DT_INIT { Some(elf_dyn.clone().d_val() as usize) } else { None }
     });
@@ -314,44 +724,44 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
         (Some(init_func_array), Some(init_func_array_sz)) => {
             let init_func_range =
                 elf_vaddr_size_to_offset_range::<E>(elf_data, init_func_array, init_func_array_sz)
-                .expect("Init array data out of bounds");
+                .map_err(|_| malformed("init array data out of bounds"))?;
             let elf_init_funcs = &elf_data[init_func_range];
-            let parse = E::from_ei_data(elf_data[EI_DATA]).unwrap();
             let mut offset = 0;
             while offset < elf_init_funcs.len() {
-                initializers.push(parse.parse_u64_at(&mut offset, elf_init_funcs).unwrap())
+                initializers.push(parse_word_at(parse, class, &mut offset, elf_init_funcs)
+                    .map_err(|_| malformed("truncated init array"))?)
             }
         }
         (None, None) => (),
-        _ => panic!("Expected dynamic table to have both or neither of DT_INIT_ARRAY and DT_INIT_ARRAYSZ")
+        _ => return Err(malformed("expected dynamic table to have both or neither of DT_INIT_ARRAY and DT_INIT_ARRAYSZ"))
     }
     let mut finalizers = Vec::new();
     match (elf_dynamic_fini_array, elf_dynamic_fini_arraysz) {
         (Some(fini_func_array), Some(fini_func_array_sz)) => {
             let fini_func_range =
                 elf_vaddr_size_to_offset_range::<E>(elf_data, fini_func_array, fini_func_array_sz)
-                .expect("Fini array data out of bounds");
+                .map_err(|_| malformed("fini array data out of bounds"))?;
             let elf_fini_funcs = &elf_data[fini_func_range];
-            let parse = E::from_ei_data(elf_data[EI_DATA]).unwrap();
             let mut offset = 0;
             while offset < elf_fini_funcs.len() {
-                finalizers.push(parse.parse_u64_at(&mut offset, elf_fini_funcs).unwrap())
+                finalizers.push(parse_word_at(parse, class, &mut offset, elf_fini_funcs)
+                    .map_err(|_| malformed("truncated fini array"))?)
             }
         }
         (None, None) => (),
-        _ => panic!("Expected dynamic table to have both or neither of DT_FINI_ARRAY and DT_FINI_ARRAYSZ")
+        _ => return Err(malformed("expected dynamic table to have both or neither of DT_FINI_ARRAY and DT_FINI_ARRAYSZ"))
     }
     if let Some(init_func) = elf_dynamic_fini { finalizers.push(init_func as u64) }
     let mut image_names = elf_dynamic.iter().filter_map(|elf_dyn| {
         if elf_dyn.d_tag == DT_SONAME {
             Some(elf_dynsyms_strs
                 .get(elf_dyn.clone().d_val() as usize)
-                .expect("Invalid DT_SONAME name")
-                .to_owned())
+                .map(|name| name.to_owned())
+                .map_err(parse_error))
         } else {
             None
         }
-    }).collect::<Vec<_>>();
+    }).collect::<Result<Vec<_>, _>>()?;
     if image_names.is_empty() { // DT_SONAMEs take priority
         if let Some(name) = soname { image_names.push(name.to_owned()) };
     }
@@ -359,13 +769,14 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
         // If PT_INTERP exists, it specifies a path to the external interpreter.
         if elf_segment.p_type == PT_INTERP {
             let path = elf_file.segment_data(&elf_segment).ok()
-                .and_then(|data| String::from_utf8(data[..data.len() - 1].to_owned()).ok())
-                .expect("Invalid PT_INTERP path");
-            Some(Interpreter::External(path))
+                .and_then(|data| data.split_last())
+                .and_then(|(_, data)| String::from_utf8(data.to_owned()).ok())
+                .ok_or_else(|| malformed("invalid PT_INTERP path"));
+            Some(path.map(Interpreter::External))
         } else {
             None
         }
-    }).unwrap_or_else(|| {
+    }).transpose()?.unwrap_or(
         if elf_file.ehdr.e_entry != 0 {
             // If PT_INTERP does not exist (and this is an ET_DYN), but there is an entry point, then this object is
             // its own interpreter. Record the values required to invoke it according to the kernel ABI later, once
@@ -392,18 +803,26 @@ pub fn parse_elf<E: EndianParse>(elf_data: &[u8], soname: Option<&str>) -> Resul
             // Probably just a shared library.
             Interpreter::Absent
         }
-    });
+    );
     let entry = elf_file.ehdr.e_entry;
     Ok(Image {
         machine,
+        byte_order,
+        word_size,
         alignment,
         segments,
         tls_image,
+        relro_ranges,
+        stack_requirements: vec![stack_requirement],
+        eh_frame_hdrs,
+        notes,
+        gnu_properties,
         symbols,
         relocations,
         initializers,
         finalizers,
         dependencies,
+        runpaths,
         image_names,
         interpreter,
         entry,
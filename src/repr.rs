@@ -1,114 +1,578 @@
 use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use elf::abi::{EM_386, EM_AARCH64, EM_X86_64};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Byte order of the words in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// Size of the addresses in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WordSize {
+    Bits32,
+    Bits64,
+}
+
+impl WordSize {
+    /// Returns the size of a word in bytes.
+    pub fn bytes(self) -> u64 {
+        match self {
+            WordSize::Bits32 => 4,
+            WordSize::Bits64 => 8,
+        }
+    }
+}
+
+/// Memory protection of a loaded segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LoadMode {
     ReadOnly,
     ReadWrite,
     ReadExecute,
 }
 
-#[derive(Debug, Clone)]
+/// A segment that is loaded into memory (`PT_LOAD`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoadSegment {
-    pub addr: u64, // virtual address, relative to object base
-    pub size: u64, // size in virtual memory
-    pub data: Vec<u8>, // data to load at [addr..addr+size); can be smaller than size in virtual memory
+    /// Virtual address of the segment, relative to the object base.
+    pub addr: u64,
+    /// Size of the segment in virtual memory.
+    pub size: u64,
+    /// Data loaded at `addr..addr + size`. It can be shorter than `size`, in which case the rest is zero-filled.
+    #[serde(with = "crate::save::hex_bytes")]
+    pub data: Vec<u8>,
+    /// Memory protection of the segment.
     pub mode: LoadMode,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A range of virtual addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressRange {
+    /// Virtual address of the start of the range, relative to the object base.
+    pub addr: u64,
+    /// Size of the range in bytes.
+    pub size: u64,
+}
+
+/// A range of virtual addresses that came from one of the merged images.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRange {
+    /// Name of the image that the range came from, for diagnostics.
+    pub image_name: String,
+    /// Virtual address of the start of the range, relative to the object base.
+    pub addr: u64,
+    /// Size of the range in bytes.
+    pub size: u64,
+}
+
+/// What a symbol refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SymbolKind {
+    /// A function (`STT_FUNC`).
     Code,
+    /// An indirect function (`STT_GNU_IFUNC`); the value is the address of a function returning the address of
+    /// the implementation.
+    Ifunc,
+    /// A data object (`STT_OBJECT`).
     Data,
+    /// A thread-local variable (`STT_TLS`); the value is an offset within the TLS block of the image.
+    Tls,
+    /// Anything else (`STT_NOTYPE`).
     Unknown,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How a symbol participates in dynamic linking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SymbolScope {
+    /// Defined by the image, and only used by its own relocations (`STB_LOCAL`).
     Local,
+    /// Defined by the image, and visible to other images (`STB_GLOBAL`).
     Global,
+    /// Not defined by the image, and must be defined by another image.
     Import,
+    /// Defined by the image if the value is not zero, and otherwise may be left undefined (`STB_WEAK`).
     Weak,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Which images can refer to a symbol defined by an image (`STV_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymbolVisibility {
+    /// Visible to other images, and references to it can be interposed (`STV_DEFAULT`).
+    Default,
+    /// Visible to other images, but references from the defining image cannot be interposed (`STV_PROTECTED`).
+    Protected,
+    /// Not visible to other images (`STV_HIDDEN`).
+    Hidden,
+    /// Not visible to other images, with processor-specific meaning (`STV_INTERNAL`).
+    Internal,
+}
+
+/// The stack that an image requires (`PT_GNU_STACK`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackRequirement {
+    /// Name of the image that has the requirement, for diagnostics.
+    pub image_name: String,
+    /// Whether the image requires an executable stack, which it also does if it doesn't say on some machines.
+    pub executable: bool,
+    /// Size of the stack that the image requires, or zero if the default size is enough.
+    pub size: u64,
+}
+
+/// A note (`PT_NOTE`), other than a GNU property note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    /// Name of the image that the note came from.
+    pub image_name: String,
+    /// Owner of the note, e.g. `GNU`.
+    pub name: String,
+    /// Type of the note, which is specific to its owner (`n_type`).
+    pub note_type: u32,
+    /// Contents of the note, without padding.
+    #[serde(with = "crate::save::hex_bytes")]
+    pub desc: Vec<u8>,
+}
+
+/// A GNU property (`NT_GNU_PROPERTY_TYPE_0`), which describes a feature that an image supports or requires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GnuProperty {
+    /// Type of the property (`GNU_PROPERTY_*`).
+    pub property_type: u32,
+    /// Contents of the property, without padding.
+    #[serde(with = "crate::save::hex_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// The initialization image for thread-local storage (`PT_TLS`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsImage {
+    /// Initial contents of the TLS block, including the zero-initialized part.
+    #[serde(with = "crate::save::hex_bytes")]
+    pub data: Vec<u8>,
+    /// Alignment of the TLS block, which is a power of 2.
+    pub alignment: u64,
+}
+
+/// The version of a symbol (GNU symbol versioning).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolVersion {
+    /// Name of the version, e.g. `GLIBC_2.14`.
+    pub name: String,
+    /// Whether the version is ignored when the symbol is looked up without a version.
+    pub hidden: bool,
+    /// For imports, the name of the image that must define the version.
+    pub file: Option<String>,
+}
+
+/// A dynamic symbol, either defined by the image or imported into it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Symbol {
+    /// Name of the symbol, without its version.
     pub name: String,
+    /// Version of the symbol, if it has one.
+    pub version: Option<SymbolVersion>,
+    /// What the symbol refers to.
     pub kind: SymbolKind,
+    /// How the symbol participates in dynamic linking.
     pub scope: SymbolScope,
+    /// Which images can refer to the symbol.
+    pub visibility: SymbolVisibility,
+    /// Virtual address of the symbol, relative to the object base, or zero if it is undefined.
     pub value: u64,
+    /// Size of the object that the symbol refers to, or zero if unknown.
     pub size: u64,
+    /// Whether the value is absolute rather than relative to the object base.
     pub abs: bool,
 }
 
-#[derive(Debug, Clone)]
+/// Returns the name of a symbol qualified with its version, e.g. `memcpy@GLIBC_2.14`.
+pub fn versioned_symbol_name(name: &str, version: Option<&SymbolVersion>) -> String {
+    match version {
+        Some(version) => format!("{}@{}", name, version.name),
+        None => name.to_owned(),
+    }
+}
+
+impl Symbol {
+    /// Returns the name of the symbol qualified with its version. Relocations refer to symbols by this name, since
+    /// an image can have several symbols with the same name and different versions.
+    pub fn versioned_name(&self) -> String {
+        versioned_symbol_name(&self.name, self.version.as_ref())
+    }
+
+    // Makes the symbol refer to a definition from another image. Everything but the name and the version of
+    // the symbol comes from the definition, including its visibility, which is re-emitted unchanged.
+    fn define_as(&mut self, definition: &Symbol) {
+        self.kind = definition.kind;
+        self.scope = definition.scope;
+        self.visibility = definition.visibility;
+        self.value = definition.value;
+        self.size = definition.size;
+        self.abs = definition.abs;
+    }
+
+    // Once an import is resolved, the image that was expected to define it is no longer relevant.
+    fn clear_version_file(&mut self) {
+        if let Some(version) = self.version.as_mut() {
+            version.file = None;
+        }
+    }
+
+    // Checks whether a reference to one of the symbols can be resolved by the other one, assuming the names match.
+    // A versioned reference is resolved by the same version, or by an unversioned definition (the dynamic loader
+    // allows this if the defining image has no versions at all); an unversioned reference is resolved by
+    // an unversioned definition, or by the default (not hidden) version.
+    fn version_matches(&self, other: &Symbol) -> bool {
+        match (&self.version, &other.version) {
+            (Some(self_version), Some(other_version)) => self_version.name == other_version.name,
+            (Some(version), None) | (None, Some(version)) => !version.hidden,
+            (None, None) => true,
+        }
+    }
+}
+
+/// The value that is written to a relocated location, in machine-independent terms.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RelocationTarget {
-    // R_X86_64_64
-    // R_X86_64_GLOB_DAT
-    // R_X86_64_JUMP_SLOT
-    // = S + A
+    /// S + A (`R_X86_64_64`, `R_X86_64_GLOB_DAT`, `R_X86_64_JUMP_SLOT`).
     Symbol { symbol: String, addend: i64 },
-    // R_X86_64_RELATIVE
-    // = B + A
+    /// B + A (`R_X86_64_RELATIVE`).
     Base { addend: i64 },
-    // R_X86_64_COPY
+    /// The contents of S (`R_X86_64_COPY`).
     Copy { symbol: String },
-    // R_X86_64_NONE
+    /// The index of the TLS module defining S, or of this one; without S, the next word holds the offset within
+    /// the TLS block (`R_X86_64_DTPMOD64`).
+    TlsModule { symbol: Option<String> },
+    /// S + A, where S is an offset within the TLS block; without S, A is an offset within the TLS block of this
+    /// module (`R_X86_64_DTPOFF64`).
+    TlsOffset { symbol: Option<String>, addend: i64 },
+    /// S + A - TP, where S is an offset within the TLS block; without S, A is an offset within the TLS block of
+    /// this module (`R_X86_64_TPOFF64`).
+    ThreadPointerOffset { symbol: Option<String>, addend: i64 },
+    /// The address returned by the function at B + resolver (`R_X86_64_IRELATIVE`).
+    IRelative { resolver: u64 },
+    /// Nothing (`R_X86_64_NONE`).
     None,
     // ... to be continued?
 
-    ElfSpecific(u32), // any that doesn't need and/or can't be portably processed
+    /// Any relocation that doesn't need to be, or can't be, processed portably, by its ELF type.
+    ElfSpecific(u32),
+}
+
+impl RelocationTarget {
+    /// Returns the versioned name of the symbol the relocation refers to, if any.
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            RelocationTarget::Symbol { symbol, .. } |
+            RelocationTarget::Copy { symbol } |
+            RelocationTarget::TlsModule { symbol: Some(symbol) } |
+            RelocationTarget::TlsOffset { symbol: Some(symbol), .. } |
+            RelocationTarget::ThreadPointerOffset { symbol: Some(symbol), .. } => Some(symbol),
+            _ => None
+        }
+    }
+
+    fn symbol_mut(&mut self) -> Option<&mut String> {
+        match self {
+            RelocationTarget::Symbol { symbol, .. } |
+            RelocationTarget::Copy { symbol } |
+            RelocationTarget::TlsModule { symbol: Some(symbol) } |
+            RelocationTarget::TlsOffset { symbol: Some(symbol), .. } |
+            RelocationTarget::ThreadPointerOffset { symbol: Some(symbol), .. } => Some(symbol),
+            _ => None
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// A dynamic relocation, applied by the dynamic loader.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Relocation {
+    /// Virtual address of the relocated location, relative to the object base.
     pub offset: u64,
+    /// Value written to the relocated location.
     pub target: RelocationTarget,
 }
 
-#[derive(Debug, Clone)]
+/// The program that loads an image into memory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Interpreter {
+    /// The image is a shared library.
     Absent,
+    /// The image is an executable that requests the interpreter at this path via `PT_INTERP`.
     External(String),
+    /// The image is an executable that includes its interpreter, with the ELF header of the interpreter at `base`
+    /// and its entry point at `entry`. Only the first `segments` program headers are reported to the interpreter.
     Internal { base: u64, entry: u64, segments: usize },
 }
 
-#[derive(Debug, Clone)]
+/// A dynamically linked executable or shared library, lifted from an ELF file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Image {
-    pub machine: u16, // ELF machine
-    pub alignment: u64, // integer that is a power of 2
-    pub segments: Vec<LoadSegment>, // sorted in ascending order
-    pub tls_image: Option<Vec<u8>>,
+    /// ELF machine (`EM_*`).
+    pub machine: u16,
+    /// Byte order of addresses and relocated locations.
+    pub byte_order: ByteOrder,
+    /// Size of addresses and relocated locations.
+    pub word_size: WordSize,
+    /// Alignment of the segments, which is a power of 2.
+    pub alignment: u64,
+    /// Loaded segments, sorted by address.
+    pub segments: Vec<LoadSegment>,
+    /// Initialization image for thread-local storage, if the image has any.
+    pub tls_image: Option<TlsImage>,
+    /// Ranges that are made read-only after relocation, one for each of the merged images that has one.
+    pub relro_ranges: Vec<ImageRange>,
+    /// Stack requirements, one for each of the merged images.
+    pub stack_requirements: Vec<StackRequirement>,
+    /// Exception handling search tables (`PT_GNU_EH_FRAME`), one for each of the merged images that has one.
+    pub eh_frame_hdrs: Vec<ImageRange>,
+    /// Notes of all of the merged images.
+    pub notes: Vec<Note>,
+    /// GNU properties that hold for the image as a whole, sorted by type.
+    pub gnu_properties: Vec<GnuProperty>,
+    /// Dynamic symbols, both defined and imported.
     pub symbols: Vec<Symbol>,
+    /// Dynamic relocations.
     pub relocations: Vec<Relocation>,
+    /// Addresses of the functions called when the image is loaded (`DT_INIT_ARRAY`), in order.
     pub initializers: Vec<u64>,
+    /// Addresses of the functions called when the image is unloaded (`DT_FINI_ARRAY`), in order.
     pub finalizers: Vec<u64>,
-    pub dependencies: Vec<String>, // requests images by name
-    pub image_names: Vec<String>, // requested via dependencies
+    /// Names of the images that this image requires (`DT_NEEDED`), in order.
+    pub dependencies: Vec<String>,
+    /// Where to look for dependencies (`DT_RUNPATH`); not emitted.
+    pub runpaths: Vec<String>,
+    /// Names by which dependencies request the image (`DT_SONAME`), one for each of the merged images.
+    pub image_names: Vec<String>,
+    /// The program that loads the image into memory.
     pub interpreter: Interpreter,
+    /// Entry point of the image, relative to the object base, or zero if it has none.
     pub entry: u64,
 }
 
 impl Image {
+    /// Returns the name that identifies the image in diagnostics.
     pub fn display_image_name(&self) -> &str {
         self.image_names.first().map(|name| &name[..]).unwrap_or("<unnamed>")
     }
 
+    /// Returns the range of virtual addresses occupied by the image, rounded to its alignment.
     pub fn segment_bounds(&self) -> (u64, u64) {
         match (self.segments.first(), self.segments.last()) {
             (Some(first), Some(last)) =>
-                (first.addr, ((last.addr + last.size - 1) | (self.alignment - 1)) + 1),
+                (first.addr, (last.addr + last.size).next_multiple_of(self.alignment.max(1))),
             _ => (0, 0)
         }
     }
 
-    pub fn rebase(&mut self, offset: u64) {
-        assert!(offset % self.alignment == 0, "Rebase offset must be aligned");
+    /// Reads bytes at a virtual address, if they are all within one of the segments.
+    pub fn read_bytes(&self, addr: u64, size: u64) -> Option<Vec<u8>> {
+        let segment = self.segments.iter().find(|segment|
+            addr >= segment.addr && addr + size <= segment.addr + segment.size)?;
+        let range_begin = (addr - segment.addr) as usize;
+        // The part of the segment past the end of its data is zero-initialized.
+        Some((range_begin..range_begin + size as usize)
+            .map(|index| segment.data.get(index).copied().unwrap_or(0))
+            .collect())
+    }
+
+    /// Reads a word at a virtual address, if it is within one of the segments.
+    pub fn read_word(&self, addr: u64) -> Option<u64> {
+        let bytes = self.read_bytes(addr, self.word_size.bytes())?.into_iter();
+        Some(match self.byte_order {
+            ByteOrder::Little => bytes.rev().fold(0, |word, byte| (word << 8) | byte as u64),
+            ByteOrder::Big => bytes.fold(0, |word, byte| (word << 8) | byte as u64),
+        })
+    }
+
+    /// Writes a word at a virtual address, if it is within one of the segments.
+    pub fn write_word(&mut self, addr: u64, word: u64) -> Option<()> {
+        let word_size = self.word_size.bytes();
+        let byte_order = self.byte_order;
+        let segment = self.segments.iter_mut().find(|segment|
+            addr >= segment.addr && addr + word_size <= segment.addr + segment.size)?;
+        let range_begin = (addr - segment.addr) as usize;
+        let range_end = range_begin + word_size as usize;
+        if segment.data.len() < range_end {
+            segment.data.resize(range_end, 0);
+        }
+        let bytes = match byte_order {
+            ByteOrder::Little => word.to_le_bytes()[..word_size as usize].to_vec(),
+            ByteOrder::Big => word.to_be_bytes()[8 - word_size as usize..].to_vec(),
+        };
+        segment.data[range_begin..range_end].copy_from_slice(&bytes);
+        Some(())
+    }
+
+    // Moves the TLS block of the image up within the TLS block of the merged image by `offset`. All of the offsets
+    // that are in the symbols and the relocations can be adjusted, but the offsets relative to the thread pointer
+    // that are encoded in the code (for the local exec TLS model) cannot.
+    fn shift_tls(&mut self, offset: u64) -> Result<(), Error> {
+        if offset == 0 { return Ok(()) }
+        for symbol in self.symbols.iter_mut() {
+            if symbol.kind == SymbolKind::Tls && symbol.scope != SymbolScope::Import {
+                symbol.value += offset;
+            }
+        }
+        for relocation in self.relocations.iter_mut() {
+            match relocation.target {
+                RelocationTarget::TlsOffset { symbol: None, ref mut addend } |
+                RelocationTarget::ThreadPointerOffset { symbol: None, ref mut addend } =>
+                    *addend += offset as i64,
+                _ => ()
+            }
+        }
+        let word_size = self.word_size.bytes();
+        let module_offsets = self.relocations.iter().filter_map(|relocation| {
+            if let RelocationTarget::TlsModule { symbol: None } = relocation.target {
+                Some(relocation.offset + word_size)
+            } else {
+                None
+            }
+        }).collect::<Vec<_>>();
+        let image_name = self.display_image_name().to_owned();
+        for module_offset in module_offsets {
+            // The `tls_index` structure consists of the module index and the offset within the module's TLS block,
+            // and in the local dynamic model, the offset is zero and `__tls_get_addr` returns the block's address.
+            // In either case, adjusting the offset results in the right address. (If the offset is computed by
+            // a relocation instead, the relocation will overwrite it.)
+            let invalid = || Error::InvalidRelocation {
+                image: image_name.clone(),
+                offset: module_offset - word_size,
+                reason: "TLS offset out of bounds".to_owned()
+            };
+            let tls_offset = self.read_word(module_offset).ok_or_else(invalid)?;
+            self.write_word(module_offset, tls_offset.wrapping_add(offset)).ok_or_else(invalid)?;
+        }
+        Ok(())
+    }
+
+    // Merges the GNU properties of the source into the GNU properties of the target, the same way as a static
+    // linker combines the properties of its input objects. A property that claims a feature (e.g. the x86 IBT and
+    // SHSTK bits, which enable CET) is only kept if both images claim it, since the merged image can only use the
+    // feature if all of its code supports it. A property that records a requirement is kept if either image has it.
+    fn merge_gnu_properties(&mut self, target: &mut Image) {
+        let machine = target.machine;
+        let mut property_types: Vec<u32> = self.gnu_properties.iter()
+            .chain(target.gnu_properties.iter())
+            .map(|property| property.property_type)
+            .collect();
+        property_types.sort_unstable();
+        property_types.dedup();
+        let combine = |left: &[u8], right: &[u8], op: fn(u8, u8) -> u8| {
+            left.iter().zip(right.iter()).map(|(&left, &right)| op(left, right)).collect::<Vec<u8>>()
+        };
+        let mut merged = Vec::new();
+        for property_type in property_types {
+            let source_data = self.gnu_properties.iter()
+                .find(|property| property.property_type == property_type)
+                .map(|property| &property.data);
+            let target_data = target.gnu_properties.iter()
+                .find(|property| property.property_type == property_type)
+                .map(|property| &property.data);
+            let data = match (gnu_property_merge_mode(machine, property_type), source_data, target_data) {
+                (GnuPropertyMerge::And, Some(source_data), Some(target_data))
+                        if source_data.len() == target_data.len() =>
+                    Some(combine(source_data, target_data, |left, right| left & right))
+                        .filter(|data| data.iter().any(|&byte| byte != 0)),
+                (GnuPropertyMerge::Or | GnuPropertyMerge::OrIfAll, Some(source_data), Some(target_data))
+                        if source_data.len() == target_data.len() =>
+                    Some(combine(source_data, target_data, |left, right| left | right)),
+                (GnuPropertyMerge::Or, Some(data), None) | (GnuPropertyMerge::Or, None, Some(data)) =>
+                    Some(data.clone()),
+                (GnuPropertyMerge::Equal, Some(source_data), Some(target_data)) if source_data == target_data =>
+                    Some(source_data.clone()),
+                _ => None,
+            };
+            if data.as_ref() != target_data {
+                info!("merge_into: GNU property {:#x} changed from {:02x?} to {:02x?}",
+                    property_type, target_data, data);
+            }
+            if let Some(data) = data {
+                merged.push(GnuProperty { property_type, data });
+            }
+        }
+        self.gnu_properties.clear();
+        target.gnu_properties = merged;
+    }
+
+    // Merges the TLS image of the source into the TLS image of the target, such that the offsets of the target
+    // relative to the thread pointer remain the same. (The offsets of the source relative to the thread pointer
+    // are not used, since the source is expected to be a shared library using a dynamic TLS model.)
+    fn merge_tls(&mut self, target: &mut Image) -> Result<(), Error> {
+        let (source_tls, target_tls) = match (&self.tls_image, &target.tls_image) {
+            (Some(source_tls), Some(target_tls)) => (source_tls.clone(), target_tls.clone()),
+            (_, None) => { target.tls_image = self.tls_image.take(); return Ok(()) }
+            (None, _) => return Ok(())
+        };
+        let alignment = source_tls.alignment.max(target_tls.alignment);
+        let (source_offset, target_offset);
+        if target.machine == EM_X86_64 || target.machine == EM_386 {
+            // With TLS variant II, the thread pointer points just past the end of the TLS block, which is aligned.
+            // The target is placed at the end, and is preceded by the source and enough padding that the end of
+            // the target (rounded up to its own alignment) is aligned to the combined alignment.
+            let target_aligned_size = (target_tls.data.len() as u64).next_multiple_of(target_tls.alignment);
+            source_offset = 0;
+            target_offset = (source_tls.data.len() as u64 + target_aligned_size).next_multiple_of(alignment)
+                - target_aligned_size;
+        } else {
+            // With TLS variant I, the TLS block is placed after the thread pointer, which is aligned. The target is
+            // placed at the beginning, and is followed by the source.
+            if target.machine == EM_AARCH64 && alignment > target_tls.alignment.max(16) {
+                // On AArch64, the TLS block is placed after a 16-byte TCB, and an alignment greater than that would
+                // move it.
+                return Err(Error::IncompatibleImages {
+                    source: self.display_image_name().to_owned(),
+                    target: target.display_image_name().to_owned(),
+                    reason: "TLS alignment would change offsets relative to the thread pointer".to_owned()
+                })
+            }
+            source_offset = (target_tls.data.len() as u64).next_multiple_of(source_tls.alignment);
+            target_offset = 0;
+        }
+        debug!("merge_into: placing source TLS block at {:+#x} and target TLS block at {:+#x}",
+            source_offset, target_offset);
+        self.shift_tls(source_offset)?;
+        target.shift_tls(target_offset)?;
+        let source_range = source_offset as usize..source_offset as usize + source_tls.data.len();
+        let target_range = target_offset as usize..target_offset as usize + target_tls.data.len();
+        let mut data = vec![0; source_range.end.max(target_range.end)];
+        data[source_range].copy_from_slice(&source_tls.data);
+        data[target_range].copy_from_slice(&target_tls.data);
+        target.tls_image = Some(TlsImage { data, alignment });
+        Ok(())
+    }
+
+    /// Moves the image up in the address space by `offset`, which must be a multiple of the alignment.
+    pub fn rebase(&mut self, offset: u64) -> Result<(), Error> {
+        // An alignment of 0 means the same as an alignment of 1, just like `p_align`.
+        if !offset.is_multiple_of(self.alignment.max(1)) {
+            return Err(Error::MisalignedRebase {
+                image: self.display_image_name().to_owned(),
+                offset,
+                alignment: self.alignment,
+            })
+        }
         for segment in self.segments.iter_mut() {
             segment.addr += offset;
         }
+        for relro_range in self.relro_ranges.iter_mut() {
+            relro_range.addr += offset;
+        }
+        // The contents of `.eh_frame_hdr` are position independent, so only its location changes.
+        for eh_frame_hdr in self.eh_frame_hdrs.iter_mut() {
+            eh_frame_hdr.addr += offset;
+        }
         for symbol in self.symbols.iter_mut() {
-            // The intermediate representation currently doesn't include absolute symbols.
-            if symbol.value != 0 {
+            // The intermediate representation currently doesn't include absolute symbols. TLS symbols aren't addresses
+            // and aren't affected by rebasing.
+            if symbol.value != 0 && symbol.kind != SymbolKind::Tls {
                 symbol.value += offset;
             }
         }
@@ -117,8 +581,13 @@ impl Image {
             match relocation.target {
                 RelocationTarget::Base { ref mut addend } =>
                     *addend += offset as i64,
+                RelocationTarget::IRelative { ref mut resolver } =>
+                    *resolver += offset,
                 RelocationTarget::Symbol { .. } |
                 RelocationTarget::Copy { .. } |
+                RelocationTarget::TlsModule { .. } |
+                RelocationTarget::TlsOffset { .. } |
+                RelocationTarget::ThreadPointerOffset { .. } |
                 RelocationTarget::None |
                 RelocationTarget::ElfSpecific(_) => ()
             }
@@ -137,140 +606,209 @@ impl Image {
             },
         }
         self.entry += offset;
+        Ok(())
     }
 
-    pub fn merge_into(mut self, target: &mut Image) {
-        // Check that the two images can be merged.
-        assert!(self.machine == target.machine);
-        assert!(self.alignment == target.alignment);
-        eprintln!("merge_into: merging source image {} into target image {}",
+    /// Merges this image into `target`, placing it above all of the segments of `target`.
+    ///
+    /// If the images cannot be merged, an error is returned; `target` is left unchanged if the error is due to
+    /// the images being incompatible, but may have been partially updated otherwise.
+    pub fn merge_into(mut self, target: &mut Image) -> Result<(), Error> {
+        // Check that the two images can be merged. This is done before anything is changed, so that the target
+        // image remains intact if the source image is rejected.
+        let incompatible = |reason: &str| Error::IncompatibleImages {
+            source: self.display_image_name().to_owned(),
+            target: target.display_image_name().to_owned(),
+            reason: reason.to_owned()
+        };
+        if self.machine != target.machine {
+            return Err(incompatible("different machine"))
+        }
+        if self.byte_order != target.byte_order {
+            return Err(incompatible("different byte order"))
+        }
+        if self.word_size != target.word_size {
+            return Err(incompatible("different word size"))
+        }
+        if self.alignment != target.alignment {
+            return Err(incompatible("different segment alignment"))
+        }
+        match (&self.interpreter, &target.interpreter) {
+            (Interpreter::Absent, Interpreter::Absent | Interpreter::External(_)) |
+            (Interpreter::Internal { .. }, Interpreter::External(_)) => (),
+            _ => return Err(Error::InterpreterConflict {
+                source: self.display_image_name().to_owned(),
+                target: target.display_image_name().to_owned(),
+            })
+        }
+        info!("merge_into: merging source image {} into target image {}",
             self.display_image_name(), target.display_image_name());
         // Relocate this image to be fully above the target.
         let (_target_begin, target_end) = target.segment_bounds();
-        eprintln!("merge_into: rebasing source image by +{:#x}", target_end);
-        self.rebase(target_end);
+        debug!("merge_into: rebasing source image by +{:#x}", target_end);
+        self.rebase(target_end)?;
+        // Merge this image's TLS image. This must be done while the segments are still separate.
+        self.merge_tls(target)?;
         // Merge this image's segments.
         target.segments.append(&mut self.segments);
-        if self.tls_image.is_some() {
-            if target.tls_image.is_none() {
-                target.tls_image = self.tls_image.take();
-            } else {
-                panic!("Merging TLS images is not implemented");
-            }
-        }
+        target.relro_ranges.append(&mut self.relro_ranges);
+        target.stack_requirements.append(&mut self.stack_requirements);
+        target.eh_frame_hdrs.append(&mut self.eh_frame_hdrs);
+        target.notes.append(&mut self.notes);
+        self.merge_gnu_properties(target);
         match (&self.interpreter, &mut target.interpreter) {
             (Interpreter::Absent, Interpreter::Absent) |
             (Interpreter::Absent, Interpreter::External(..)) => {
                 // Merging executable + library or library + library
-                self.merge_dynamic(target);
+                self.merge_dynamic(target)?;
             }
             (source_interpreter @ Interpreter::Internal { .. },
              target_interpreter @ Interpreter::External(_)) => {
                 // Merging interpreter + executable
-                eprintln!("merge_into: embedding the source image into target object as its interpreter");
+                info!("merge_into: embedding the source image into target object as its interpreter");
                 *target_interpreter = source_interpreter.clone();
             }
-            (source_interpreter, target_interpreter) =>
-                panic!("Cannot merge source object with interpreter {:?} into target object with interpreter {:?}",
-                    source_interpreter, target_interpreter)
+            _ => unreachable!() // checked above
         }
+        Ok(())
     }
 
-    fn merge_dynamic(mut self, target: &mut Image) {
-        // Index the target image's symbol table.
-        let mut target_symbol_map = HashMap::new();
+    fn merge_dynamic(mut self, target: &mut Image) -> Result<(), Error> {
+        let source_image_name = self.display_image_name().to_owned();
+        // Index the target image's symbol table. There can be several symbols with the same name if they have
+        // different versions.
+        let mut target_symbol_map: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut target_versioned_names = HashSet::new();
         for (symbol_index, symbol) in target.symbols.iter().enumerate() {
-            if target_symbol_map.insert(symbol.name.clone(), symbol_index).is_some() {
-                panic!("Duplicate symbol {:?} in target image", symbol.name.as_str());
+            if !target_versioned_names.insert(symbol.versioned_name()) {
+                return Err(Error::DuplicateSymbol {
+                    image: target.display_image_name().to_owned(),
+                    symbol: symbol.versioned_name()
+                })
             }
+            target_symbol_map.entry(symbol.name.clone()).or_default().push(symbol_index);
         }
-        // Merge symbols.
+        // Merge symbols. If a source symbol is merged with a target symbol that has a different versioned name,
+        // the source relocations referring to it must be renamed.
+        let mut renamed_symbols = HashMap::new();
+        // If a source symbol that cannot be interposed is merged with a target symbol, the source relocations
+        // referring to it must be resolved to the source definition instead.
+        let mut locally_bound_symbols = HashMap::new();
         let mut apply_copy_relocs_later = Vec::new();
         for source_symbol in self.symbols.into_iter() {
             let symbol_name = source_symbol.name.to_owned();
-            let target_symbol = target_symbol_map.get(&symbol_name).map(|index| &mut target.symbols[*index]);
+            let target_image_name = target.display_image_name().to_owned();
+            let target_symbol_index = target_symbol_map.get(&symbol_name).and_then(|symbol_indices| {
+                symbol_indices.iter().copied().find(|&index| source_symbol.version_matches(&target.symbols[index]))
+            });
+            let target_versioned_name = target_symbol_index.map(|index| target.symbols[index].versioned_name());
+            if let Some(ref target_versioned_name) = target_versioned_name {
+                if source_symbol.versioned_name() != *target_versioned_name {
+                    renamed_symbols.insert(source_symbol.versioned_name(), target_versioned_name.clone());
+                }
+            }
+            // Symbol resolution decisions carry structured data, so that they can be recorded by e.g. build systems.
+            let log_resolution = |provider: &str, message: std::fmt::Arguments| {
+                debug!(symbol = symbol_name.as_str(), source_image = source_image_name.as_str(),
+                    target_image = target_image_name.as_str(), provider; "{}", message)
+            };
+            let target_symbol = target_symbol_index.map(|index| &mut target.symbols[index]);
             match (source_symbol, target_symbol) {
                 (source_symbol, None) => {
-                    // eprintln!("merge_into: adding new symbol {:?}", &symbol_name);
-                    target_symbol_map.insert(symbol_name.clone(), target.symbols.len());
+                    // debug!("merge_into: adding new symbol {:?}", &symbol_name);
+                    target_symbol_map.entry(symbol_name.clone()).or_default().push(target.symbols.len());
                     target.symbols.push(source_symbol);
                 }
                 (_source_symbol @ Symbol { scope: SymbolScope::Weak, value: 0, .. },
                  Some(_target_symbol @ &mut Symbol { scope: SymbolScope::Weak, value: 0, .. })) => (),
                 (_source_symbol @ Symbol { scope: SymbolScope::Weak, value: 0, .. },
                  Some(_target_symbol @ &mut Symbol { scope: SymbolScope::Weak, .. })) => {
-                    eprintln!("merge_into: replacing source weak symbol {:?} with target weak symbol", &symbol_name);
+                    log_resolution("target", format_args!("merge_into: replacing source weak symbol {:?} with target weak symbol", &symbol_name));
                 }
                 (source_symbol @ Symbol { scope: SymbolScope::Weak, .. },
                  Some(target_symbol @ &mut Symbol { scope: SymbolScope::Weak, value: 0, .. })) => {
-                    eprintln!("merge_into: using source weak symbol {:?} to resolve target missing weak symbol", &symbol_name);
-                    target_symbol.scope = source_symbol.scope;
-                    target_symbol.kind = source_symbol.kind;
-                    target_symbol.value = source_symbol.value;
+                    log_resolution("source", format_args!("merge_into: using source weak symbol {:?} to resolve target missing weak symbol", &symbol_name));
+                    target_symbol.define_as(&source_symbol);
+                    target_symbol.clear_version_file();
                 }
                 (source_symbol @ Symbol { scope: SymbolScope::Weak, .. },
                  Some(target_symbol @ &mut Symbol { scope: SymbolScope::Weak, .. })) => {
-                    eprintln!("merge_into: using source weak symbol {:?} to resolve target missing weak symbol", &symbol_name);
-                    target_symbol.scope = source_symbol.scope;
-                    target_symbol.kind = source_symbol.kind;
-                    target_symbol.value = source_symbol.value;
+                    log_resolution("source", format_args!("merge_into: using source weak symbol {:?} to resolve target missing weak symbol", &symbol_name));
+                    target_symbol.define_as(&source_symbol);
                 }
                 (source_symbol @ Symbol { scope: SymbolScope::Global | SymbolScope::Weak, .. },
                  Some(target_symbol @ &mut Symbol { scope: SymbolScope::Import, .. })) => {
-                    eprintln!("merge_into: using source symbol {:?} to resolve target import", &symbol_name);
-                    target_symbol.scope = source_symbol.scope;
-                    target_symbol.kind = source_symbol.kind;
-                    target_symbol.value = source_symbol.value;
+                    log_resolution("source", format_args!("merge_into: using source symbol {:?} to resolve target import", &symbol_name));
+                    target_symbol.define_as(&source_symbol);
+                    target_symbol.clear_version_file();
                 },
                 (_source_symbol @ Symbol { scope: SymbolScope::Import, .. },
                  Some(_target_symbol @ &mut Symbol { scope: SymbolScope::Global | SymbolScope::Weak, .. })) => {
-                    eprintln!("merge_into: using target symbol {:?} to resolve source import", &symbol_name);
+                    log_resolution("target", format_args!("merge_into: using target symbol {:?} to resolve source import", &symbol_name));
                 },
                 (source_symbol @ Symbol { scope: SymbolScope::Global, .. },
                  Some(target_symbol @ &mut Symbol { scope: SymbolScope::Weak, value: 0, .. })) => {
-                    eprintln!("merge_into: using source global symbol {:?} to resolve target missing weak symbol", &symbol_name);
-                    target_symbol.scope = source_symbol.scope;
-                    target_symbol.kind = source_symbol.kind;
-                    target_symbol.value = source_symbol.value;
+                    log_resolution("source", format_args!("merge_into: using source global symbol {:?} to resolve target missing weak symbol", &symbol_name));
+                    target_symbol.define_as(&source_symbol);
+                    target_symbol.clear_version_file();
                 },
                 (Symbol { scope: SymbolScope::Weak, value: 0, .. },
                  Some(&mut Symbol { scope: SymbolScope::Global, .. })) => {
-                    eprintln!("merge_into: using target global symbol {:?} to resolve source missing weak symbol", &symbol_name);
+                    log_resolution("target", format_args!("merge_into: using target global symbol {:?} to resolve source missing weak symbol", &symbol_name));
                 },
                 (source_symbol, Some(target_symbol @ &mut Symbol { .. }))
                         if symbol_name == "_init" || symbol_name == "_fini" => {
                     if self.image_names.iter().find(|name| **name == "libc.so").is_some() {
-                        eprintln!("merge_into: forcing target special symbol {:?} to come from libc", &symbol_name);
+                        log_resolution("source", format_args!("merge_into: forcing target special symbol {:?} to come from libc", &symbol_name));
+                        target_symbol.define_as(&source_symbol);
                         target_symbol.scope = SymbolScope::Global;
-                        target_symbol.kind = source_symbol.kind;
-                        target_symbol.value = source_symbol.value;
                     } else {
-                        eprintln!("merge_into: ignoring source special symbol {:?}", &symbol_name)
+                        log_resolution("target", format_args!("merge_into: ignoring source special symbol {:?}", &symbol_name))
                     }
                 }
                 (source_symbol @ Symbol { scope: SymbolScope::Global, kind: SymbolKind::Data, .. },
                  Some(target_symbol @ &mut Symbol { scope: SymbolScope::Global, kind: SymbolKind::Data, .. }))
                         if source_symbol.size == target_symbol.size => {
-                    eprintln!("merge_into: replacing source global data symbol {:?} with the same target global data symbol", &symbol_name);
+                    log_resolution("target", format_args!("merge_into: replacing source global data symbol {:?} with the same target global data symbol", &symbol_name));
                     for (reloc_index, reloc) in target.relocations.iter().enumerate() {
                         if let Relocation { target: RelocationTarget::Copy { symbol: copy_symbol_name }, .. } = &reloc {
-                            if symbol_name == *copy_symbol_name {
+                            if target_versioned_name.as_ref() == Some(copy_symbol_name) {
                                 apply_copy_relocs_later.push((reloc_index, source_symbol.clone()));
                             }
                         }
                     }
                 },
+                (source_symbol @ Symbol { scope: SymbolScope::Global | SymbolScope::Weak, .. },
+                 Some(&mut Symbol { scope: SymbolScope::Global | SymbolScope::Weak, .. }))
+                        if source_symbol.visibility != SymbolVisibility::Default => {
+                    log_resolution("local", format_args!("merge_into: binding source references to non-default visibility symbol {:?} locally", &symbol_name));
+                    locally_bound_symbols.insert(source_symbol.versioned_name(), source_symbol);
+                },
+                (_source_symbol @ Symbol { scope: SymbolScope::Global | SymbolScope::Weak, .. },
+                 Some(&mut Symbol { scope: SymbolScope::Global | SymbolScope::Weak,
+                                    visibility: SymbolVisibility::Protected, .. })) => {
+                    log_resolution("target", format_args!("merge_into: keeping target protected symbol {:?} instead of source symbol", &symbol_name));
+                },
                 (source_symbol, Some(target_symbol)) if &source_symbol == target_symbol => (),
-                (source_symbol, Some(target_symbol)) => {
-                    panic!("Cannot merge source symbol {:?} into target symbol {:?}",
-                        source_symbol, target_symbol)
+                (_source_symbol, Some(_target_symbol)) => {
+                    return Err(Error::SymbolConflict {
+                        source: source_image_name,
+                        target: target_image_name,
+                        symbol: symbol_name
+                    })
                 }
             }
         }
         // Apply copy relocations, if any were triggered.
         for (reloc_index, source_symbol) in apply_copy_relocs_later.into_iter() {
+            let target_image_name = target.display_image_name().to_owned();
             let target_reloc = &mut target.relocations[reloc_index];
-            eprintln!("merge_into: applying copy relocation for symbol {:?}: copying {:#x}{:+#x} => {:#x}",
+            let copy_error = || Error::CopyRelocation {
+                image: target_image_name.clone(),
+                symbol: source_symbol.name.clone(),
+                offset: target_reloc.offset
+            };
+            debug!("merge_into: applying copy relocation for symbol {:?}: copying {:#x}{:+#x} => {:#x}",
                 &source_symbol.name, source_symbol.value, source_symbol.size, target_reloc.offset);
             let source_data = target.segments.iter().find_map(|segment| {
                 if source_symbol.value >= segment.addr &&
@@ -285,7 +823,7 @@ impl Image {
                 } else {
                     None
                 }
-            }).expect("Failed to find source segment for copy relocation");
+            }).ok_or_else(copy_error)?;
             for segment in target.segments.iter_mut() {
                 if target_reloc.offset >= segment.addr &&
                         target_reloc.offset + source_symbol.size <= segment.addr + segment.size {
@@ -295,34 +833,223 @@ impl Image {
                         segment.data.resize(range_end, 0);
                     }
                     segment.data.get_mut(range_begin..range_end)
-                        .expect("Failed to slice target data for copy relocation")
+                        .ok_or_else(copy_error)?
                         .copy_from_slice(&source_data);
                 }
             }
             target_reloc.target = RelocationTarget::None;
         }
         // Merge relocations. Relocations can never be removed, even if they refer to the self.
+        for relocation in self.relocations.iter_mut() {
+            let local_symbol = relocation.target.symbol().and_then(|symbol| locally_bound_symbols.get(symbol));
+            if let Some(local_symbol) = local_symbol {
+                // The symbol values have already been rebased (and shifted within the TLS block, if applicable).
+                let local_value = local_symbol.value as i64;
+                relocation.target = match relocation.target {
+                    RelocationTarget::Symbol { addend: 0, .. } if local_symbol.kind == SymbolKind::Ifunc =>
+                        RelocationTarget::IRelative { resolver: local_symbol.value },
+                    RelocationTarget::Symbol { addend, .. }
+                            if local_symbol.kind != SymbolKind::Ifunc && !local_symbol.abs =>
+                        RelocationTarget::Base { addend: local_value + addend },
+                    RelocationTarget::TlsModule { .. } =>
+                        RelocationTarget::TlsModule { symbol: None },
+                    RelocationTarget::TlsOffset { addend, .. } =>
+                        RelocationTarget::TlsOffset { symbol: None, addend: local_value + addend },
+                    RelocationTarget::ThreadPointerOffset { addend, .. } =>
+                        RelocationTarget::ThreadPointerOffset { symbol: None, addend: local_value + addend },
+                    _ => return Err(Error::LocalBinding {
+                        image: source_image_name,
+                        symbol: local_symbol.versioned_name(),
+                        offset: relocation.offset
+                    })
+                };
+            } else if let Some(symbol) = relocation.target.symbol_mut() {
+                if let Some(target_symbol) = renamed_symbols.get(symbol) {
+                    *symbol = target_symbol.clone();
+                }
+            }
+        }
         target.relocations.append(&mut self.relocations);
         // Merge initializers and finalizers.
         target.initializers.append(&mut self.initializers);
-        // Merge dependencies.
-        let mut target_dependency_set = HashSet::new();
-        for target_dependency in target.dependencies.iter() {
-            target_dependency_set.insert(target_dependency.clone());
-        }
+        // Merge dependencies. The order of dependencies is the order of DT_NEEDED entries, which determines
+        // the order of symbol lookup, so it is preserved: the dependencies of the target come first, followed by
+        // the new dependencies of the source.
         for source_dependency in self.dependencies.into_iter() {
-            if target.image_names.iter().find(|&image_name| *image_name == source_dependency).is_some() { continue }
-            if target_dependency_set.insert(source_dependency.clone()) {
-                eprintln!("merge_into: adding new dependency {:?}", source_dependency);
+            if target.image_names.contains(&source_dependency) { continue }
+            if !target.dependencies.contains(&source_dependency) {
+                info!("merge_into: adding new dependency {:?}", source_dependency);
+                target.dependencies.push(source_dependency);
             }
         }
-        for source_image_name in self.image_names.iter() {
-            if target_dependency_set.remove(source_image_name) {
-                eprintln!("merge_into: removing extinguished dependency {:?}", &source_image_name);
+        target.dependencies.retain(|target_dependency| {
+            let is_extinguished = self.image_names.contains(target_dependency);
+            if is_extinguished {
+                info!("merge_into: removing extinguished dependency {:?}", target_dependency);
             }
-        }
-        target.dependencies = target_dependency_set.into_iter().collect::<Vec<_>>();
+            !is_extinguished
+        });
         // Merge image names.
         target.image_names.append(&mut self.image_names);
+        Ok(())
+    }
+}
+
+// How the values of a GNU property are combined when two images are merged.
+enum GnuPropertyMerge {
+    And, // present in both images; the bits are intersected
+    Or, // present in either image; the bits are combined
+    OrIfAll, // present in both images; the bits are combined
+    Equal, // present in both images with the same value
+}
+
+const GNU_PROPERTY_AARCH64_FEATURE_1_AND: u32 = 0xc0000000;
+const GNU_PROPERTY_UINT32_AND_LO: u32 = 0xb0000000;
+const GNU_PROPERTY_UINT32_AND_HI: u32 = 0xb0007fff;
+const GNU_PROPERTY_UINT32_OR_LO: u32 = 0xb0008000;
+const GNU_PROPERTY_UINT32_OR_HI: u32 = 0xb000ffff;
+const GNU_PROPERTY_X86_UINT32_AND_LO: u32 = 0xc0000002;
+const GNU_PROPERTY_X86_UINT32_AND_HI: u32 = 0xc0007fff;
+const GNU_PROPERTY_X86_UINT32_OR_LO: u32 = 0xc0008000;
+const GNU_PROPERTY_X86_UINT32_OR_HI: u32 = 0xc000ffff;
+const GNU_PROPERTY_X86_UINT32_OR_AND_LO: u32 = 0xc0010000;
+const GNU_PROPERTY_X86_UINT32_OR_AND_HI: u32 = 0xc0017fff;
+
+fn gnu_property_merge_mode(machine: u16, property_type: u32) -> GnuPropertyMerge {
+    let x86 = machine == EM_386 || machine == EM_X86_64;
+    match property_type {
+        GNU_PROPERTY_UINT32_AND_LO..=GNU_PROPERTY_UINT32_AND_HI => GnuPropertyMerge::And,
+        GNU_PROPERTY_UINT32_OR_LO..=GNU_PROPERTY_UINT32_OR_HI => GnuPropertyMerge::Or,
+        GNU_PROPERTY_X86_UINT32_AND_LO..=GNU_PROPERTY_X86_UINT32_AND_HI if x86 => GnuPropertyMerge::And,
+        GNU_PROPERTY_X86_UINT32_OR_LO..=GNU_PROPERTY_X86_UINT32_OR_HI if x86 => GnuPropertyMerge::Or,
+        GNU_PROPERTY_X86_UINT32_OR_AND_LO..=GNU_PROPERTY_X86_UINT32_OR_AND_HI if x86 => GnuPropertyMerge::OrIfAll,
+        GNU_PROPERTY_AARCH64_FEATURE_1_AND if machine == EM_AARCH64 => GnuPropertyMerge::And,
+        _ => GnuPropertyMerge::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use elf::abi::EM_RISCV;
+
+    use super::*;
+
+    // An image with a TLS block of `size` bytes, a TLS symbol at its start, and relocations that refer to it by
+    // offset, as well as to an imported TLS symbol. The word after the module relocation holds offset 2.
+    fn tls_image(machine: u16, name: &str, size: usize, alignment: u64) -> Image {
+        let tls_symbol = |name: String, scope, value| Symbol {
+            name,
+            version: None,
+            kind: SymbolKind::Tls,
+            scope,
+            visibility: SymbolVisibility::Default,
+            value,
+            size: 0,
+            abs: false,
+        };
+        Image {
+            machine,
+            byte_order: ByteOrder::Little,
+            word_size: WordSize::Bits64,
+            alignment: 0x1000,
+            segments: vec![LoadSegment {
+                addr: 0,
+                size: 0x1000,
+                data: vec![0, 0, 0, 0, 0, 0, 0, 0, 2],
+                mode: LoadMode::ReadWrite
+            }],
+            tls_image: Some(TlsImage { data: vec![0xaa; size], alignment }),
+            relro_ranges: Vec::new(),
+            stack_requirements: Vec::new(),
+            eh_frame_hdrs: Vec::new(),
+            notes: Vec::new(),
+            gnu_properties: Vec::new(),
+            symbols: vec![
+                tls_symbol(format!("{}_tls", name), SymbolScope::Global, 0),
+                tls_symbol("errno".to_owned(), SymbolScope::Import, 0),
+            ],
+            relocations: vec![
+                Relocation { offset: 0, target: RelocationTarget::TlsModule { symbol: None } },
+                Relocation { offset: 0x10, target: RelocationTarget::TlsOffset { symbol: None, addend: 4 } },
+                Relocation { offset: 0x18, target: RelocationTarget::ThreadPointerOffset { symbol: None, addend: 0 } },
+                Relocation {
+                    offset: 0x20,
+                    target: RelocationTarget::ThreadPointerOffset { symbol: Some("errno".to_owned()), addend: 0 }
+                },
+            ],
+            initializers: Vec::new(),
+            finalizers: Vec::new(),
+            dependencies: Vec::new(),
+            runpaths: Vec::new(),
+            image_names: vec![name.to_owned()],
+            interpreter: Interpreter::Absent,
+            entry: 0,
+        }
+    }
+
+    // Checks that the TLS block of an image was moved by `offset`, and that references to other modules were not.
+    fn assert_tls_shifted(image: &Image, offset: u64) {
+        assert_eq!(image.symbols[0].value, offset);
+        assert_eq!(image.symbols[1].value, 0);
+        assert_eq!(image.read_word(8), Some(2 + offset));
+        assert_eq!(image.relocations[1].target,
+            RelocationTarget::TlsOffset { symbol: None, addend: 4 + offset as i64 });
+        assert_eq!(image.relocations[2].target,
+            RelocationTarget::ThreadPointerOffset { symbol: None, addend: offset as i64 });
+        assert_eq!(image.relocations[3].target,
+            RelocationTarget::ThreadPointerOffset { symbol: Some("errno".to_owned()), addend: 0 });
+    }
+
+    // With variant II, the target keeps its offsets relative to the end of the block, so it is the one that moves
+    // within the block, past the source and the padding that aligns its end.
+    #[test]
+    fn merge_tls_variant_ii() {
+        let mut source = tls_image(EM_X86_64, "source", 12, 32);
+        let mut target = tls_image(EM_X86_64, "target", 8, 8);
+        source.merge_tls(&mut target).unwrap();
+        assert_tls_shifted(&source, 0);
+        assert_tls_shifted(&target, 24);
+        let tls_image = target.tls_image.unwrap();
+        assert_eq!(tls_image.alignment, 32);
+        assert_eq!(tls_image.data.len(), 32);
+        assert_eq!(tls_image.data[12..24], [0; 12]);
+    }
+
+    // With variant I, the target keeps its offsets relative to the start of the block, and the source follows it.
+    #[test]
+    fn merge_tls_variant_i() {
+        for machine in [EM_AARCH64, EM_RISCV] {
+            let mut source = tls_image(machine, "source", 8, 16);
+            let mut target = tls_image(machine, "target", 20, 8);
+            source.merge_tls(&mut target).unwrap();
+            assert_tls_shifted(&source, 32);
+            assert_tls_shifted(&target, 0);
+            let tls_image = target.tls_image.unwrap();
+            assert_eq!(tls_image.alignment, 16);
+            assert_eq!(tls_image.data.len(), 40);
+            assert_eq!(tls_image.data[20..32], [0; 12]);
+        }
+    }
+
+    // On AArch64, the TLS block follows a 16-byte TCB, so aligning it further would move the target's variables.
+    #[test]
+    fn merge_tls_aarch64_alignment() {
+        let mut source = tls_image(EM_AARCH64, "source", 8, 32);
+        let mut target = tls_image(EM_AARCH64, "target", 8, 8);
+        match source.merge_tls(&mut target) {
+            Err(Error::IncompatibleImages { reason, .. }) =>
+                assert_eq!(reason, "TLS alignment would change offsets relative to the thread pointer"),
+            result => panic!("unexpected result {:?}", result),
+        }
+        // It is fine if the target is already aligned as much, since its offsets already account for the padding.
+        let mut source = tls_image(EM_AARCH64, "source", 8, 32);
+        let mut target = tls_image(EM_AARCH64, "target", 8, 32);
+        source.merge_tls(&mut target).unwrap();
+        assert_tls_shifted(&source, 32);
+        // Other machines that use variant I have no such restriction.
+        let mut source = tls_image(EM_RISCV, "source", 8, 32);
+        let mut target = tls_image(EM_RISCV, "target", 8, 8);
+        source.merge_tls(&mut target).unwrap();
+        assert_tls_shifted(&source, 32);
     }
 }
@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use elf::abi::{EM_386, EM_AARCH64, EM_RISCV, EM_X86_64};
+use elf::endian::AnyEndian;
+use log::info;
+
+use crate::repr::*;
+use crate::error::Error;
+use crate::parse::parse_elf;
+use crate::save::is_saved_image;
+
+/// Where to look for the dependencies of images.
+#[derive(Debug, Clone)]
+pub struct SearchPaths {
+    /// Directory prepended to absolute paths found in images.
+    pub sysroot: PathBuf,
+    /// Directories searched before the search paths of images.
+    pub library_dirs: Vec<PathBuf>,
+}
+
+impl Default for SearchPaths {
+    fn default() -> Self {
+        SearchPaths { sysroot: PathBuf::from("/"), library_dirs: Vec::new() }
+    }
+}
+
+// The directories that the dynamic loaders of musl and glibc search by default, along with the ones that are usually
+// listed in `/etc/ld.so.conf` on distributions with multiarch (Debian) or 64-bit (Fedora) library directories.
+fn default_dirs(image: &Image) -> Vec<String> {
+    let mut dirs = Vec::new();
+    let multiarch = match image.machine {
+        EM_X86_64 => Some("x86_64-linux-gnu"),
+        EM_AARCH64 => Some("aarch64-linux-gnu"),
+        EM_RISCV => Some("riscv64-linux-gnu"),
+        EM_386 => Some("i386-linux-gnu"),
+        _ => None,
+    };
+    if let Some(multiarch) = multiarch {
+        dirs.extend([format!("/lib/{}", multiarch), format!("/usr/lib/{}", multiarch)]);
+    }
+    if image.word_size == WordSize::Bits64 {
+        dirs.extend(["/lib64".to_owned(), "/usr/lib64".to_owned()]);
+    }
+    dirs.extend(["/lib", "/usr/local/lib", "/usr/lib"].map(str::to_owned));
+    dirs
+}
+
+impl SearchPaths {
+    fn in_sysroot(&self, path: &str) -> PathBuf {
+        match path.strip_prefix('/') {
+            Some(relative_path) => self.sysroot.join(relative_path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    // Looks for a dependency the same way the dynamic loader would, except that `-L` directories come first,
+    // `LD_LIBRARY_PATH` is ignored, and `/etc/ld.so.*` is replaced with the directories that it usually lists.
+    fn find_dependency(&self, name: &str, image: &Image, image_path: &Path) -> Option<PathBuf> {
+        if name.contains('/') {
+            let path = self.in_sysroot(name);
+            return if path.is_file() { Some(path) } else { None }
+        }
+        // `$ORIGIN` is the directory containing the image (after resolving symlinks), which is a path on the host
+        // rather than in the sysroot.
+        let origin = image_path.parent().unwrap_or(Path::new(".")).to_string_lossy();
+        let image_dirs = image.runpaths.iter().map(|runpath| {
+            if runpath.contains("$ORIGIN") || runpath.contains("${ORIGIN}") {
+                PathBuf::from(runpath.replace("${ORIGIN}", &origin).replace("$ORIGIN", &origin))
+            } else {
+                self.in_sysroot(runpath)
+            }
+        });
+        self.library_dirs.iter().cloned()
+            .chain(image_dirs)
+            .chain(default_dirs(image).into_iter().map(|dir| self.in_sysroot(&dir)))
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+}
+
+/// Reads and parses the ELF file at `path`, naming the image after the file if it has no `DT_SONAME`.
+///
+/// The file can also be an image saved with [`Image::save`], which is loaded as-is.
+pub fn load_image(path: &Path) -> Result<Image, Error> {
+    let data = std::fs::read(path).map_err(|error| Error::Io { path: path.to_owned(), error })?;
+    if is_saved_image(&data) {
+        return Image::load(&data).map_err(|reason| Error::InvalidSavedImage { path: path.to_owned(), reason })
+    }
+    let soname = path.file_name().and_then(|name| name.to_str());
+    parse_elf::<AnyEndian>(&data[..], soname)
+}
+
+/// Loads the images at `paths`, followed by all of their dependencies, and finally the interpreter requested by
+/// the first image, if any.
+///
+/// Merging the returned images in order gives the same result as listing the same files by hand. Each file is loaded
+/// only once, even if it is reachable through several names.
+pub fn load_closure(paths: &[PathBuf], search_paths: &SearchPaths) -> Result<Vec<Image>, Error> {
+    let canonicalize = |path: &Path|
+        path.canonicalize().map_err(|error| Error::Io { path: path.to_owned(), error });
+    let mut images: Vec<(PathBuf, Image)> = Vec::new();
+    let mut loaded = HashSet::new();
+    // The interpreter is merged last even if it is one of the inputs, or if an image depends on it. The latter is
+    // the case with musl, where the dynamic loader is also `libc.so`; in that case, it is loaded under the name of
+    // the dependency, so that the dependency is extinguished when it's merged.
+    let mut interpreter: Option<(PathBuf, PathBuf, bool)> = None;
+    for path in paths {
+        let canonical_path = canonicalize(path)?;
+        if !loaded.insert(canonical_path.clone()) {
+            continue
+        }
+        if let Some((interpreter_canonical_path, interpreter_path, _found)) = interpreter.as_mut() {
+            if *interpreter_canonical_path == canonical_path {
+                *interpreter_path = path.clone();
+                continue
+            }
+        }
+        let image = load_image(path)?;
+        if images.is_empty() {
+            if let Interpreter::External(interpreter_path) = &image.interpreter {
+                let interpreter_path = search_paths.in_sysroot(interpreter_path);
+                interpreter = Some((canonicalize(&interpreter_path)?, interpreter_path, false));
+            }
+        }
+        images.push((canonical_path, image));
+    }
+    // Dependencies are loaded in breadth-first order, the same as the dynamic loader does.
+    let mut image_index = 0;
+    while image_index < images.len() {
+        let (image_path, image) = &images[image_index];
+        let mut dependency_paths = Vec::new();
+        for dependency in image.dependencies.iter() {
+            let dependency_path = search_paths.find_dependency(dependency, image, image_path)
+                .ok_or_else(|| Error::MissingDependency {
+                    image: image.display_image_name().to_owned(),
+                    dependency: dependency.clone()
+                })?;
+            let canonical_path = canonicalize(&dependency_path)?;
+            if let Some((interpreter_canonical_path, interpreter_path, found)) = interpreter.as_mut() {
+                if *interpreter_canonical_path == canonical_path {
+                    if !*found {
+                        *interpreter_path = dependency_path;
+                        *found = true;
+                    }
+                    continue
+                }
+            }
+            if loaded.insert(canonical_path.clone()) {
+                info!("load_closure: found dependency {:?} of {} at {}",
+                    dependency, image.display_image_name(), dependency_path.display());
+                dependency_paths.push((canonical_path, dependency_path));
+            }
+        }
+        for (canonical_path, dependency_path) in dependency_paths {
+            images.push((canonical_path, load_image(&dependency_path)?));
+        }
+        image_index += 1;
+    }
+    if let Some((interpreter_canonical_path, interpreter_path, _found)) = interpreter {
+        info!("load_closure: found interpreter at {}", interpreter_path.display());
+        images.push((interpreter_canonical_path, load_image(&interpreter_path)?));
+    }
+    Ok(images.into_iter().map(|(_canonical_path, image)| image).collect())
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use crate::repr::Image;
+
+/// The version of the saved form of an [`Image`]. It changes whenever `Image` or any of the types in it change,
+/// since images saved by another version of Superlinker cannot be loaded.
+pub const SAVED_IMAGE_VERSION: u32 = 3;
+
+// Binary saved images start with this, followed by the version as a little-endian 32-bit word, and the image.
+const SAVED_IMAGE_MAGIC: &[u8; 8] = b"\x7fSLIMG\0\0";
+
+// JSON saved images are objects with the version next to the image.
+const SAVED_IMAGE_JSON_FORMAT: &str = "superlinker-image";
+
+// Byte arrays (such as the contents of segments) are saved as hex strings in JSON, which is much more compact and
+// readable than an array of numbers, and as-is in the binary form.
+pub(crate) mod hex_bytes {
+    use std::fmt;
+
+    use serde::{de, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl de::Visitor<'_> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a hex string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, hex: &str) -> Result<Vec<u8>, E> {
+            if !hex.len().is_multiple_of(2) {
+                return Err(E::custom("hex string has an odd length"))
+            }
+            (0..hex.len()).step_by(2)
+                .map(|index| hex.get(index..index + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(|| E::custom("invalid hex string"))
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_owned())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BytesVisitor)
+        } else {
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+}
+
+/// How an [`Image`] is saved: JSON is meant to be read and edited, and binary is compact and fast to load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// A JSON object with the version and the image.
+    Json,
+    /// A magic number and the version, followed by the image in the `postcard` format.
+    Binary,
+}
+
+#[derive(Serialize)]
+struct SavedImageRef<'image> {
+    format: &'static str,
+    version: u32,
+    image: &'image Image,
+}
+
+#[derive(Deserialize)]
+struct SavedImageHeader {
+    format: String,
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct SavedImage {
+    image: Image,
+}
+
+/// Returns true if the data looks like a saved image rather than an ELF file.
+pub fn is_saved_image(data: &[u8]) -> bool {
+    data.starts_with(SAVED_IMAGE_MAGIC) || data.trim_ascii_start().starts_with(b"{")
+}
+
+impl Image {
+    /// Saves the image in a form that can be loaded with [`Image::load`], even if it was partially merged.
+    pub fn save(&self, format: SaveFormat) -> Vec<u8> {
+        match format {
+            SaveFormat::Json => {
+                let saved_image = SavedImageRef {
+                    format: SAVED_IMAGE_JSON_FORMAT,
+                    version: SAVED_IMAGE_VERSION,
+                    image: self
+                };
+                let mut data = serde_json::to_vec_pretty(&saved_image).expect("images can be serialized");
+                data.push(b'\n');
+                data
+            }
+            SaveFormat::Binary => {
+                let mut data = SAVED_IMAGE_MAGIC.to_vec();
+                data.extend_from_slice(&SAVED_IMAGE_VERSION.to_le_bytes());
+                postcard::to_extend(self, data).expect("images can be serialized")
+            }
+        }
+    }
+
+    /// Loads an image saved with [`Image::save`] in either format.
+    pub fn load(data: &[u8]) -> Result<Image, String> {
+        let check_version = |version: u32| if version == SAVED_IMAGE_VERSION {
+            Ok(())
+        } else {
+            Err(format!("saved image has version {}, but only version {} is supported", version, SAVED_IMAGE_VERSION))
+        };
+        if let Some(data) = data.strip_prefix(SAVED_IMAGE_MAGIC) {
+            let (version, data) = data.split_first_chunk::<4>().ok_or("saved image is truncated")?;
+            check_version(u32::from_le_bytes(*version))?;
+            let (image, rest) = postcard::take_from_bytes::<Image>(data).map_err(|error| error.to_string())?;
+            if !rest.is_empty() {
+                return Err("saved image has trailing data".to_owned())
+            }
+            Ok(image)
+        } else {
+            // The version is checked before the image is deserialized, so that images of other versions are
+            // reported as such, instead of as having the wrong shape.
+            let header: SavedImageHeader = serde_json::from_slice(data).map_err(|error| error.to_string())?;
+            if header.format != SAVED_IMAGE_JSON_FORMAT {
+                return Err(format!("unknown format {:?}", header.format))
+            }
+            check_version(header.version)?;
+            let saved_image: SavedImage = serde_json::from_slice(data).map_err(|error| error.to_string())?;
+            Ok(saved_image.image)
+        }
+    }
+}
@@ -0,0 +1,44 @@
+// Runs the command line tool on the fixtures, checking that its commands agree with each other.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use elf::abi::EM_X86_64;
+use superlinker::*;
+
+fn tree(name: &str) -> PathBuf {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cli").join(name);
+    if root.exists() {
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    std::fs::create_dir_all(&root).unwrap();
+    root
+}
+
+fn superlinker(args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_superlinker")).args(args).output().unwrap();
+    assert!(output.status.success(), "superlinker failed: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+// Each input is described the same as it would be saved on its own.
+#[test]
+fn inspect_json_is_saved_image() {
+    let root = tree("inspect_json_is_saved_image");
+    let executable_path = root.join(common::EXECUTABLE_NAME).to_str().unwrap().to_owned();
+    let library_path = root.join(common::LIBRARY_NAME).to_str().unwrap().to_owned();
+    std::fs::write(&executable_path, emit_elf(&common::executable(EM_X86_64), &EmitOptions::default()).unwrap())
+        .unwrap();
+    std::fs::write(&library_path, emit_elf(&common::library(EM_X86_64), &EmitOptions::default()).unwrap()).unwrap();
+    let mut saved_images = Vec::new();
+    for path in [&executable_path, &library_path] {
+        let saved_path = format!("{}.json", path);
+        superlinker(&["save", "--json", "-o", &saved_path, path]);
+        saved_images.extend(std::fs::read(&saved_path).unwrap());
+    }
+    let inspected = superlinker(&["inspect", "--json", &executable_path, &library_path]);
+    assert!(inspected == saved_images, "inspect --json differs from save --json:\n{}",
+        String::from_utf8_lossy(&inspected));
+}
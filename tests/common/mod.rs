@@ -0,0 +1,274 @@
+// Synthetic images that exercise as much of the intermediate representation as possible without having to build
+// anything with a cross toolchain. They are not meant to be run, only to be emitted, parsed, and merged.
+#![allow(dead_code)]
+
+use elf::abi::{EM_386, EM_AARCH64, EM_RISCV, EM_X86_64};
+use superlinker::*;
+
+pub const MACHINES: [u16; 4] = [EM_X86_64, EM_AARCH64, EM_RISCV, EM_386];
+
+pub const LIBRARY_NAME: &str = "libfixture.so";
+pub const EXECUTABLE_NAME: &str = "fixture";
+
+const NT_GNU_ABI_TAG: u32 = 1;
+const NT_GNU_BUILD_ID: u32 = 3;
+const GNU_PROPERTY_STACK_SIZE: u32 = 1;
+pub const GNU_PROPERTY_UINT32_AND_LO: u32 = 0xb0000000;
+
+pub fn word_size(machine: u16) -> WordSize {
+    if machine == EM_386 { WordSize::Bits32 } else { WordSize::Bits64 }
+}
+
+pub fn word_bytes(word_size: WordSize, word: u64) -> Vec<u8> {
+    word.to_le_bytes()[..word_size.bytes() as usize].to_vec()
+}
+
+fn u32_words(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+// A `.eh_frame_hdr` with a search table, using the same encodings as the one that is emitted.
+fn eh_frame_hdr(addr: u64, eh_frame: u64, table: &[(u64, u64)]) -> Vec<u8> {
+    let mut data = vec![/*version=*/1, /*pcrel|sdata4*/0x1b, /*udata4*/0x03, /*datarel|sdata4*/0x3b];
+    data.extend_from_slice(&((eh_frame as i64 - (addr + 4) as i64) as i32).to_le_bytes());
+    data.extend_from_slice(&(table.len() as u32).to_le_bytes());
+    for &(initial_location, fde) in table {
+        data.extend_from_slice(&((initial_location as i64 - addr as i64) as i32).to_le_bytes());
+        data.extend_from_slice(&((fde as i64 - addr as i64) as i32).to_le_bytes());
+    }
+    data
+}
+
+// Decodes a search table written by `eh_frame_hdr` or by the emitter into the address of `.eh_frame` and
+// the (initial location, FDE) pairs.
+pub fn decode_eh_frame_hdr(image: &Image, eh_frame_hdr: &ImageRange) -> (u64, Vec<(u64, u64)>) {
+    let data = image.read_bytes(eh_frame_hdr.addr, eh_frame_hdr.size).expect(".eh_frame_hdr out of bounds");
+    assert_eq!(data[..4], [1, 0x1b, 0x03, 0x3b]);
+    let read_i32 = |offset: usize| i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as i64;
+    let eh_frame = (eh_frame_hdr.addr as i64 + 4 + read_i32(4)) as u64;
+    let table = (0..read_i32(8) as usize)
+        .map(|index| {
+            let offset = 12 + index * 8;
+            ((eh_frame_hdr.addr as i64 + read_i32(offset)) as u64,
+                (eh_frame_hdr.addr as i64 + read_i32(offset + 4)) as u64)
+        })
+        .collect();
+    (eh_frame, table)
+}
+
+fn version(name: &str, hidden: bool, file: Option<&str>) -> Option<SymbolVersion> {
+    Some(SymbolVersion { name: name.to_owned(), hidden, file: file.map(str::to_owned) })
+}
+
+fn definition(name: &str, version: Option<SymbolVersion>, kind: SymbolKind, value: u64, size: u64) -> Symbol {
+    Symbol {
+        name: name.to_owned(),
+        version,
+        kind,
+        scope: SymbolScope::Global,
+        visibility: SymbolVisibility::Default,
+        value,
+        size,
+        abs: false,
+    }
+}
+
+fn import(name: &str, version: Option<SymbolVersion>) -> Symbol {
+    Symbol {
+        name: name.to_owned(),
+        version,
+        kind: SymbolKind::Code,
+        scope: SymbolScope::Import,
+        visibility: SymbolVisibility::Default,
+        value: 0,
+        size: 0,
+        abs: false,
+    }
+}
+
+fn relocation(offset: u64, target: RelocationTarget) -> Relocation {
+    Relocation { offset, target }
+}
+
+// Read-only, code, and data segments at 0, 0x1000, and 0x2000. The read-only segment starts with `.eh_frame_hdr`,
+// followed by `.eh_frame` at 0x40, and the data segment is only partially initialized.
+fn segments(word_size: WordSize, eh_frame_table: &[(u64, u64)], data_words: usize) -> Vec<LoadSegment> {
+    let mut ro_data = eh_frame_hdr(0, 0x40, eh_frame_table);
+    ro_data.resize(0x40, 0);
+    ro_data.extend_from_slice(&[0x5a; 0x20]);
+    vec![
+        LoadSegment { addr: 0x0, size: 0x100, data: ro_data, mode: LoadMode::ReadOnly },
+        LoadSegment { addr: 0x1000, size: 0x100, data: vec![0xcc; 0x40], mode: LoadMode::ReadExecute },
+        LoadSegment {
+            addr: 0x2000,
+            size: 0x100,
+            data: vec![0; data_words * word_size.bytes() as usize],
+            mode: LoadMode::ReadWrite
+        },
+    ]
+}
+
+/// A shared library with versioned definitions and imports, TLS, notes, and GNU properties.
+pub fn library(machine: u16) -> Image {
+    let word_size = word_size(machine);
+    let word = word_size.bytes();
+    let image_name = LIBRARY_NAME.to_owned();
+    Image {
+        machine,
+        byte_order: ByteOrder::Little,
+        word_size,
+        alignment: 0x1000,
+        segments: segments(word_size, &[(0x1000, 0x40), (0x1010, 0x50)], 8),
+        tls_image: Some(TlsImage { data: vec![1, 2, 3, 4, 0, 0, 0, 0], alignment: word }),
+        relro_ranges: vec![ImageRange { image_name: image_name.clone(), addr: 0x2000, size: 0x40 }],
+        stack_requirements: vec![StackRequirement { image_name: image_name.clone(), executable: false, size: 0 }],
+        eh_frame_hdrs: vec![ImageRange { image_name: image_name.clone(), addr: 0x0, size: 28 }],
+        notes: vec![
+            Note { image_name: image_name.clone(), name: "GNU".to_owned(), note_type: NT_GNU_BUILD_ID,
+                desc: vec![0xab; 20] },
+            Note { image_name: image_name.clone(), name: "GNU".to_owned(), note_type: NT_GNU_ABI_TAG,
+                desc: u32_words(&[0, 3, 2, 0]) },
+            Note { image_name: image_name.clone(), name: "Fixture".to_owned(), note_type: 0x100,
+                desc: b"fixture\0".to_vec() },
+        ],
+        gnu_properties: vec![
+            GnuProperty { property_type: GNU_PROPERTY_STACK_SIZE, data: word_bytes(word_size, 0x100000) },
+            GnuProperty { property_type: GNU_PROPERTY_UINT32_AND_LO, data: u32_words(&[3]) },
+        ],
+        symbols: vec![
+            definition("fixture_function", version("LIBFIXTURE_1", false, None), SymbolKind::Code, 0x1000, 0x10),
+            definition("fixture_function", version("LIBFIXTURE_0", true, None), SymbolKind::Code, 0x1010, 0x10),
+            Symbol {
+                visibility: SymbolVisibility::Protected,
+                ..definition("fixture_data", version("LIBFIXTURE_1", false, None), SymbolKind::Data, 0x2080, 8)
+            },
+            definition("fixture_tls", None, SymbolKind::Tls, 0, 4),
+            import("memcpy", version("GLIBC_2.14", false, Some("libc.so.6"))),
+            import("strlen", version("GLIBC_2.2.5", false, Some("libc.so.6"))),
+        ],
+        relocations: vec![
+            relocation(0x2000, RelocationTarget::Symbol { symbol: "memcpy@GLIBC_2.14".to_owned(), addend: 0 }),
+            relocation(0x2000 + word, RelocationTarget::Base { addend: 0x1000 }),
+            relocation(0x2000 + word * 2, RelocationTarget::Base { addend: 0x1010 }),
+            relocation(0x2000 + word * 3, RelocationTarget::Base { addend: 0x2080 }),
+            relocation(0x2000 + word * 5, RelocationTarget::Base { addend: 0x40 }),
+            relocation(0x2000 + word * 6, RelocationTarget::IRelative { resolver: 0x1020 }),
+            relocation(0x2000 + word * 7, RelocationTarget::TlsModule { symbol: None }),
+        ],
+        initializers: vec![],
+        finalizers: vec![],
+        dependencies: vec!["libc.so.6".to_owned()],
+        runpaths: vec![],
+        image_names: vec![image_name],
+        interpreter: Interpreter::Absent,
+        entry: 0,
+    }
+}
+
+/// An executable that imports from [`library`] and has initializers and finalizers. It has no RELRO range, since
+/// it could not be coalesced with the one of the library when the two are merged.
+pub fn executable(machine: u16) -> Image {
+    let word_size = word_size(machine);
+    let word = word_size.bytes();
+    let image_name = EXECUTABLE_NAME.to_owned();
+    Image {
+        machine,
+        byte_order: ByteOrder::Little,
+        word_size,
+        alignment: 0x1000,
+        segments: segments(word_size, &[(0x1000, 0x40)], 4),
+        tls_image: None,
+        relro_ranges: vec![],
+        stack_requirements: vec![StackRequirement { image_name: image_name.clone(), executable: false, size: 0 }],
+        eh_frame_hdrs: vec![ImageRange { image_name: image_name.clone(), addr: 0x0, size: 20 }],
+        notes: vec![
+            Note { image_name: image_name.clone(), name: "GNU".to_owned(), note_type: NT_GNU_BUILD_ID,
+                desc: vec![0xcd; 20] },
+            Note { image_name: image_name.clone(), name: "GNU".to_owned(), note_type: NT_GNU_ABI_TAG,
+                desc: u32_words(&[0, 3, 0, 0]) },
+        ],
+        gnu_properties: vec![
+            GnuProperty { property_type: GNU_PROPERTY_UINT32_AND_LO, data: u32_words(&[1]) },
+        ],
+        symbols: vec![
+            definition("main", None, SymbolKind::Code, 0x1000, 0x20),
+            import("fixture_function", version("LIBFIXTURE_1", false, Some(LIBRARY_NAME))),
+            import("memcpy", version("GLIBC_2.14", false, Some("libc.so.6"))),
+        ],
+        relocations: vec![
+            relocation(0x2000, RelocationTarget::Symbol {
+                symbol: "fixture_function@LIBFIXTURE_1".to_owned(),
+                addend: 0
+            }),
+            relocation(0x2000 + word, RelocationTarget::Symbol { symbol: "memcpy@GLIBC_2.14".to_owned(), addend: 0 }),
+            relocation(0x2000 + word * 2, RelocationTarget::Base { addend: 0x1000 }),
+        ],
+        initializers: vec![0x1010],
+        finalizers: vec![0x1018],
+        dependencies: vec![LIBRARY_NAME.to_owned(), "libc.so.6".to_owned()],
+        runpaths: vec![],
+        image_names: vec![],
+        interpreter: Interpreter::External("/lib/ld-fixture.so.1".to_owned()),
+        entry: 0x1000,
+    }
+}
+
+/// The executable with the library merged into it.
+pub fn merged(machine: u16) -> Image {
+    let mut image = executable(machine);
+    library(machine).merge_into(&mut image).expect("cannot merge fixtures");
+    image
+}
+
+pub fn machine_name(machine: u16) -> &'static str {
+    match machine {
+        EM_X86_64 => "x86_64",
+        EM_AARCH64 => "aarch64",
+        EM_RISCV => "riscv64",
+        EM_386 => "i386",
+        _ => unreachable!(),
+    }
+}
+
+// Names the machine that a check failed for, since the checks are the same for every machine.
+struct MachineGuard(u16);
+
+impl Drop for MachineGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!("note: the check failed for {}", machine_name(self.0));
+        }
+    }
+}
+
+/// Runs `check` for every supported machine.
+pub fn for_each_machine(mut check: impl FnMut(u16)) {
+    for machine in MACHINES {
+        let _guard = MachineGuard(machine);
+        check(machine);
+    }
+}
+
+/// An image that was emitted and lifted back.
+pub struct RoundTrip {
+    pub machine: u16,
+    pub input: Image,
+    pub output: Image,
+    /// How far the segments of the input were moved by emitting it; they are placed after the ELF headers and
+    /// dynamic linking information, in the same order.
+    pub base: u64,
+}
+
+/// Emits the image made by `fixture` for every supported machine with `options`, lifts it back, and runs `check`
+/// on the result.
+pub fn for_each_round_trip(fixture: fn(u16) -> Image, options: &EmitOptions, mut check: impl FnMut(RoundTrip)) {
+    for_each_machine(|machine| {
+        let input = fixture(machine);
+        let elf_data = emit_elf(&input, options).expect("cannot emit fixture");
+        let output = parse_elf::<AnyEndian>(&elf_data, Some(input.display_image_name()))
+            .expect("cannot parse emitted fixture");
+        let first_image_segment = output.segments.len() - input.segments.len();
+        let base = output.segments[first_image_segment].addr - input.segments[0].addr;
+        check(RoundTrip { machine, input, output, base });
+    });
+}
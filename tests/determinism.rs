@@ -0,0 +1,36 @@
+// Links the fixtures several times over, checking that the output only depends on the inputs.
+
+mod common;
+
+use superlinker::*;
+
+fn link(executable_data: &[u8], library_data: &[u8]) -> Vec<u8> {
+    let mut image = parse_elf::<AnyEndian>(executable_data, Some(common::EXECUTABLE_NAME)).unwrap();
+    let library = parse_elf::<AnyEndian>(library_data, Some(common::LIBRARY_NAME)).unwrap();
+    library.merge_into(&mut image).unwrap();
+    emit_elf(&image, &EmitOptions::default()).unwrap()
+}
+
+#[test]
+fn link_is_reproducible() {
+    common::for_each_machine(|machine| {
+        let executable_data = emit_elf(&common::executable(machine), &EmitOptions::default()).unwrap();
+        let library_data = emit_elf(&common::library(machine), &EmitOptions::default()).unwrap();
+        let first = link(&executable_data, &library_data);
+        for _ in 0..4 {
+            assert!(link(&executable_data, &library_data) == first, "output differs between links");
+        }
+    });
+}
+
+#[test]
+fn dependencies_keep_their_order() {
+    common::for_each_machine(|machine| {
+        let mut image = common::executable(machine);
+        image.dependencies.extend(["libz.so.1".to_owned(), "libm.so.6".to_owned()]);
+        let mut library = common::library(machine);
+        library.dependencies.extend(["libm.so.6".to_owned(), "libdl.so.2".to_owned(), "libz.so.1".to_owned()]);
+        library.merge_into(&mut image).unwrap();
+        assert_eq!(image.dependencies, ["libc.so.6", "libz.so.1", "libm.so.6", "libdl.so.2"]);
+    });
+}
@@ -0,0 +1,57 @@
+// Compares the merged fixtures with the saved images in `tests/golden`, which keeps the on-disk schema from changing
+// by accident. After an intended change to the schema (which requires bumping `SAVED_IMAGE_VERSION`) or to merging,
+// run the tests with `SUPERLINKER_BLESS=1` to update the saved images.
+
+mod common;
+
+use std::path::PathBuf;
+
+use elf::abi::EM_X86_64;
+use superlinker::*;
+
+fn golden_path(machine: u16) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+        .join(format!("{}-merged.json", common::machine_name(machine)))
+}
+
+fn read_golden(machine: u16) -> String {
+    let path = golden_path(machine);
+    std::fs::read_to_string(&path).unwrap_or_else(|error| panic!("{}: {}", path.display(), error))
+}
+
+#[test]
+fn merged_images_match_golden() {
+    common::for_each_machine(|machine| {
+        let saved = String::from_utf8(common::merged(machine).save(SaveFormat::Json)).unwrap();
+        if std::env::var_os("SUPERLINKER_BLESS").is_some() {
+            std::fs::write(golden_path(machine), &saved).unwrap();
+        } else {
+            assert!(saved == read_golden(machine), "{} is out of date; run with SUPERLINKER_BLESS=1 to update it",
+                golden_path(machine).display());
+        }
+    });
+}
+
+#[test]
+fn golden_images_load() {
+    common::for_each_machine(|machine| {
+        let golden = read_golden(machine);
+        let image = Image::load(golden.as_bytes()).unwrap();
+        assert_eq!(String::from_utf8(image.save(SaveFormat::Json)).unwrap(), golden);
+        let reloaded = Image::load(&image.save(SaveFormat::Binary)).unwrap();
+        assert_eq!(String::from_utf8(reloaded.save(SaveFormat::Json)).unwrap(), golden);
+        // A saved image can be emitted the same as the image it was saved from.
+        assert!(emit_elf(&reloaded, &EmitOptions::default()).unwrap() ==
+            emit_elf(&common::merged(machine), &EmitOptions::default()).unwrap());
+    });
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let golden = read_golden(EM_X86_64);
+    let version = format!("\"version\": {}", SAVED_IMAGE_VERSION);
+    assert!(golden.contains(&version));
+    let older = golden.replacen(&version, &format!("\"version\": {}", SAVED_IMAGE_VERSION - 1), 1);
+    let error = Image::load(older.as_bytes()).unwrap_err();
+    assert!(error.contains("version"), "unexpected error {:?}", error);
+}
@@ -0,0 +1,117 @@
+// Merges the library into the executable after changing how a symbol is defined in each of them, checking how
+// the references of the library to its own symbols are resolved.
+
+mod common;
+
+use superlinker::*;
+
+fn symbol<'a>(image: &'a Image, versioned_name: &str) -> &'a Symbol {
+    image.symbols.iter().find(|symbol| symbol.versioned_name() == versioned_name).unwrap()
+}
+
+// The library refers to `name` from its otherwise unused data word 4.
+fn reference_from_library(library: &mut Image, name: &str, addend: i64) -> u64 {
+    let offset = 0x2000 + library.word_size.bytes() * 4;
+    let target = RelocationTarget::Symbol { symbol: name.to_owned(), addend };
+    library.relocations.push(Relocation { offset, target });
+    offset
+}
+
+// The executable defines its own `name`, of a different size than any in the library so that the two are not
+// mistaken for the same object.
+fn define_in_executable(executable: &mut Image, name: &str) {
+    executable.symbols.push(Symbol {
+        name: name.to_owned(),
+        version: None,
+        kind: SymbolKind::Data,
+        scope: SymbolScope::Global,
+        visibility: SymbolVisibility::Default,
+        value: 0x2010,
+        size: 0x10,
+        abs: false,
+    });
+}
+
+// How far the library is moved when it is merged into the executable.
+fn library_base(executable: &Image) -> u64 {
+    executable.segment_bounds().1
+}
+
+// The references of the library to its protected definition cannot be interposed, so they are bound to the
+// definition even if the executable has its own, while references from the executable are bound to the executable's
+// definition if it has one, and to the library's definition otherwise.
+#[test]
+fn protected_definition_binds_locally() {
+    common::for_each_machine(|machine| {
+        let mut image = common::executable(machine);
+        define_in_executable(&mut image, "fixture_data");
+        let base = library_base(&image);
+        let mut library = common::library(machine);
+        let offset = reference_from_library(&mut library, "fixture_data@LIBFIXTURE_1", 4);
+        library.merge_into(&mut image).unwrap();
+        let relocation = image.relocations.iter().find(|relocation| relocation.offset == base + offset).unwrap();
+        assert_eq!(relocation.target, RelocationTarget::Base { addend: (base + 0x2080 + 4) as i64 });
+        assert_eq!((symbol(&image, "fixture_data").value, symbol(&image, "fixture_data").size), (0x2010, 0x10));
+
+        let mut image = common::executable(machine);
+        image.symbols.push(Symbol {
+            name: "fixture_data".to_owned(),
+            version: Some(SymbolVersion {
+                name: "LIBFIXTURE_1".to_owned(),
+                hidden: false,
+                file: Some(common::LIBRARY_NAME.to_owned())
+            }),
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Import,
+            visibility: SymbolVisibility::Default,
+            value: 0,
+            size: 0,
+            abs: false,
+        });
+        let mut library = common::library(machine);
+        let offset = reference_from_library(&mut library, "fixture_data@LIBFIXTURE_1", 4);
+        library.merge_into(&mut image).unwrap();
+        // The import of the executable is resolved to the protected definition, which keeps its visibility and size.
+        let definition = symbol(&image, "fixture_data@LIBFIXTURE_1");
+        assert_eq!((definition.scope, definition.visibility), (SymbolScope::Global, SymbolVisibility::Protected));
+        assert_eq!((definition.value, definition.size), (base + 0x2080, 8));
+        assert_eq!(definition.version.as_ref().unwrap().file, None);
+        let relocation = image.relocations.iter().find(|relocation| relocation.offset == base + offset).unwrap();
+        assert_eq!(relocation.target,
+            RelocationTarget::Symbol { symbol: "fixture_data@LIBFIXTURE_1".to_owned(), addend: 4 });
+    });
+}
+
+// A hidden symbol that the executable also defines is bound locally as well, which is impossible if its value is not
+// relative to the object base.
+#[test]
+fn hidden_absolute_symbol_cannot_bind_locally() {
+    common::for_each_machine(|machine| {
+        let mut image = common::executable(machine);
+        define_in_executable(&mut image, "fixture_hidden");
+        let base = library_base(&image);
+        let mut library = common::library(machine);
+        library.symbols.push(Symbol {
+            name: "fixture_hidden".to_owned(),
+            version: None,
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Global,
+            visibility: SymbolVisibility::Hidden,
+            value: 0x2090,
+            size: 8,
+            abs: false,
+        });
+        let offset = reference_from_library(&mut library, "fixture_hidden", 0);
+        let mut merged = image.clone();
+        library.clone().merge_into(&mut merged).unwrap();
+        let relocation = merged.relocations.iter().find(|relocation| relocation.offset == base + offset).unwrap();
+        assert_eq!(relocation.target, RelocationTarget::Base { addend: (base + 0x2090) as i64 });
+
+        library.symbols.last_mut().unwrap().abs = true;
+        match library.merge_into(&mut image) {
+            Err(Error::LocalBinding { symbol, offset: error_offset, .. }) =>
+                assert_eq!((symbol.as_str(), error_offset), ("fixture_hidden", base + offset)),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    });
+}
@@ -0,0 +1,114 @@
+// Finds the dependencies of the fixtures in a directory tree laid out like a root filesystem, checking that they are
+// looked up and loaded in the same order as the dynamic loader would.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use elf::abi::EM_X86_64;
+use superlinker::*;
+
+fn tree(name: &str) -> PathBuf {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("resolve").join(name);
+    if root.exists() {
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    std::fs::create_dir_all(&root).unwrap();
+    root
+}
+
+// Writes a library that depends on `dependencies`, and that is named `label` so that the test can tell which copy of
+// a library was loaded.
+fn write_library(path: &Path, label: &str, dependencies: &[&str]) {
+    let mut library = common::library(EM_X86_64);
+    library.image_names = vec![label.to_owned()];
+    library.dependencies = dependencies.iter().map(|&dependency| dependency.to_owned()).collect();
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, emit_elf(&library, &EmitOptions::default()).unwrap()).unwrap();
+}
+
+// Runpaths are not emitted, so the executable is written as a saved image instead, which is not named after its file.
+fn write_executable(path: &Path, runpaths: &[&str], dependencies: &[&str]) {
+    let mut executable = common::executable(EM_X86_64);
+    executable.image_names = vec![common::EXECUTABLE_NAME.to_owned()];
+    executable.runpaths = runpaths.iter().map(|&runpath| runpath.to_owned()).collect();
+    executable.dependencies = dependencies.iter().map(|&dependency| dependency.to_owned()).collect();
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, executable.save(SaveFormat::Binary)).unwrap();
+}
+
+fn loaded_names(paths: &[PathBuf], search_paths: &SearchPaths) -> Vec<String> {
+    load_closure(paths, search_paths).unwrap().iter()
+        .map(|image| image.display_image_name().to_owned())
+        .collect()
+}
+
+// `$ORIGIN` is the directory of the image on the host, and not in the sysroot, while other runpaths are in the sysroot.
+#[test]
+fn origin_is_expanded() {
+    let root = tree("origin");
+    let sysroot = root.join("sysroot");
+    let executable_path = root.join("app/bin/fixture");
+    write_executable(&executable_path, &["$ORIGIN/../lib", "/opt/fixture"], &["libfixture.so", "libc.so.6"]);
+    write_library(&sysroot.join("lib/ld-fixture.so.1"), "interpreter", &[]);
+    write_library(&root.join("app/lib/libfixture.so"), "origin", &[]);
+    write_library(&sysroot.join("lib/libfixture.so"), "default", &[]);
+    write_library(&sysroot.join("opt/fixture/libc.so.6"), "runpath", &[]);
+    write_library(&root.join("opt/fixture/libc.so.6"), "host", &[]);
+    let search_paths = SearchPaths { sysroot, library_dirs: Vec::new() };
+    assert_eq!(loaded_names(&[executable_path], &search_paths),
+        [common::EXECUTABLE_NAME, "origin", "runpath", "interpreter"]);
+}
+
+// Directories given by the user come first, followed by the runpaths of the image, then the multiarch directories,
+// then the 64-bit ones, and finally the ones that every dynamic loader searches.
+#[test]
+fn search_order() {
+    let root = tree("search_order");
+    let sysroot = root.join("sysroot");
+    let executable_path = root.join("fixture");
+    write_executable(&executable_path, &["/opt/fixture"], &["libc.so.6"]);
+    write_library(&sysroot.join("lib/ld-fixture.so.1"), "interpreter", &[]);
+    let dirs = [
+        root.join("user"),
+        sysroot.join("opt/fixture"),
+        sysroot.join("lib/x86_64-linux-gnu"),
+        sysroot.join("usr/lib/x86_64-linux-gnu"),
+        sysroot.join("lib64"),
+        sysroot.join("usr/lib64"),
+        sysroot.join("lib"),
+        sysroot.join("usr/local/lib"),
+        sysroot.join("usr/lib"),
+    ];
+    for dir in dirs.iter() {
+        write_library(&dir.join("libc.so.6"), &dir.display().to_string(), &[]);
+    }
+    let search_paths = SearchPaths { sysroot, library_dirs: vec![root.join("user")] };
+    for dir in dirs.iter() {
+        let names = loaded_names(std::slice::from_ref(&executable_path), &search_paths);
+        assert_eq!(names[1], dir.display().to_string());
+        std::fs::remove_file(dir.join("libc.so.6")).unwrap();
+    }
+    match load_closure(&[executable_path], &search_paths) {
+        Err(Error::MissingDependency { dependency, .. }) => assert_eq!(dependency, "libc.so.6"),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+// Dependencies are loaded breadth first, each only once, and the interpreter is loaded last even if an image
+// depends on it.
+#[test]
+fn breadth_first_order() {
+    let root = tree("breadth_first_order");
+    let sysroot = root.join("sysroot");
+    let executable_path = root.join("fixture");
+    write_executable(&executable_path, &[], &["libfixture.so", "libc.so.6"]);
+    write_library(&sysroot.join("lib/libfixture.so"), "libfixture.so", &["libz.so.1", "libc.so.6"]);
+    write_library(&sysroot.join("lib/libz.so.1"), "libz.so.1", &["libm.so.6"]);
+    write_library(&sysroot.join("lib/libm.so.6"), "libm.so.6", &["ld-fixture.so.1"]);
+    write_library(&sysroot.join("lib/libc.so.6"), "libc.so.6", &["ld-fixture.so.1"]);
+    write_library(&sysroot.join("lib/ld-fixture.so.1"), "ld-fixture.so.1", &[]);
+    let search_paths = SearchPaths { sysroot, library_dirs: Vec::new() };
+    assert_eq!(loaded_names(&[executable_path], &search_paths),
+        [common::EXECUTABLE_NAME, "libfixture.so", "libc.so.6", "libz.so.1", "libm.so.6", "ld-fixture.so.1"]);
+}
@@ -0,0 +1,401 @@
+// Emits the fixtures and lifts them back, checking that the parts of the image that the parser has to reconstruct
+// come back the same, other than being moved by the emitter.
+
+mod common;
+
+use elf::abi::{
+    DT_REL, DT_RELA, DT_RELAENT, DT_RELASZ, DT_RELENT, DT_RELSZ, EM_386, EM_X86_64, PF_R, PF_W, PF_X, PT_DYNAMIC,
+    PT_GNU_STACK, PT_LOAD, PT_NULL,
+};
+use elf::endian::AnyEndian as ElfAnyEndian;
+use elf::ElfBytes;
+use superlinker::*;
+
+const DT_RELRSZ: i64 = 35;
+const DT_RELR: i64 = 36;
+const DT_RELRENT: i64 = 37;
+
+// The library with relocations for the initial-exec and local-exec TLS models added, both by symbol and by offset
+// within its own TLS block.
+fn with_tls_relocations(machine: u16) -> Image {
+    let mut library = common::library(machine);
+    let word = library.word_size.bytes();
+    let tls_symbol = || Some("fixture_tls".to_owned());
+    let targets = [
+        RelocationTarget::TlsOffset { symbol: tls_symbol(), addend: 0 },
+        RelocationTarget::TlsOffset { symbol: None, addend: 4 },
+        RelocationTarget::ThreadPointerOffset { symbol: tls_symbol(), addend: 0 },
+        RelocationTarget::ThreadPointerOffset { symbol: None, addend: 0 },
+    ];
+    for (index, target) in targets.into_iter().enumerate() {
+        library.relocations.push(Relocation { offset: 0x2000 + word * (9 + index as u64), target });
+    }
+    library
+}
+
+fn tls_relocation_targets(image: &Image) -> Vec<RelocationTarget> {
+    let mut relocations = image.relocations.iter()
+        .filter(|relocation| matches!(relocation.target,
+            RelocationTarget::TlsOffset { .. } | RelocationTarget::ThreadPointerOffset { .. }))
+        .collect::<Vec<_>>();
+    relocations.sort_by_key(|relocation| relocation.offset);
+    relocations.into_iter().map(|relocation| relocation.target.clone()).collect()
+}
+
+// Offsets within the TLS block are not addresses, so they stay the same when the image is moved.
+#[test]
+fn tls_offsets_round_trip() {
+    common::for_each_round_trip(with_tls_relocations, &EmitOptions::default(), |round_trip| {
+        let mut expected = round_trip.input.clone();
+        expected.rebase(round_trip.base).unwrap();
+        assert_eq!(tls_relocation_targets(&expected), tls_relocation_targets(&round_trip.input));
+        let mut relocations = round_trip.output.relocations.clone();
+        relocations.sort_by_key(|relocation| relocation.offset);
+        assert_eq!(relocations, expected.relocations);
+        let tls_symbol = round_trip.output.symbols.iter().find(|symbol| symbol.name == "fixture_tls").unwrap();
+        assert_eq!((tls_symbol.kind, tls_symbol.value), (SymbolKind::Tls, 0));
+    });
+}
+
+// When merged, the offsets within the TLS block are moved along with the TLS block of the library, which follows
+// the TLS block of the executable with TLS variant I, and precedes it with TLS variant II.
+#[test]
+fn tls_offsets_merge() {
+    common::for_each_machine(|machine| {
+        let mut image = common::executable(machine);
+        image.tls_image = Some(TlsImage { data: vec![0; 16], alignment: 16 });
+        with_tls_relocations(machine).merge_into(&mut image).unwrap();
+        let tls_offset = if machine == EM_X86_64 || machine == EM_386 { 0 } else { 16 };
+        let tls_symbol = image.symbols.iter().find(|symbol| symbol.name == "fixture_tls").unwrap();
+        assert_eq!((tls_symbol.kind, tls_symbol.value), (SymbolKind::Tls, tls_offset));
+        assert_eq!(tls_relocation_targets(&image), [
+            RelocationTarget::TlsOffset { symbol: Some("fixture_tls".to_owned()), addend: 0 },
+            RelocationTarget::TlsOffset { symbol: None, addend: 4 + tls_offset as i64 },
+            RelocationTarget::ThreadPointerOffset { symbol: Some("fixture_tls".to_owned()), addend: 0 },
+            RelocationTarget::ThreadPointerOffset { symbol: None, addend: tls_offset as i64 },
+        ]);
+    });
+}
+
+fn relro_ranges(image: &Image) -> Vec<(u64, u64)> {
+    image.relro_ranges.iter().map(|relro_range| (relro_range.addr, relro_range.size)).collect()
+}
+
+// Adjacent and overlapping RELRO ranges are emitted as one.
+#[test]
+fn relro_ranges_coalesce() {
+    let fixture = |machine| {
+        let mut library = common::library(machine);
+        let image_name = library.display_image_name().to_owned();
+        library.relro_ranges = [(0x2010, 0x10), (0x2000, 0x10), (0x2018, 0x28)].into_iter()
+            .map(|(addr, size)| ImageRange { image_name: image_name.clone(), addr, size })
+            .collect();
+        library
+    };
+    common::for_each_round_trip(fixture, &EmitOptions::default(), |round_trip| {
+        assert_eq!(relro_ranges(&round_trip.output), [(0x2000 + round_trip.base, 0x40)]);
+    });
+}
+
+// The RELRO ranges of several images are separated by writable data, so all but the largest one would be left
+// writable, which has to be allowed.
+#[test]
+fn relro_ranges_of_several_images() {
+    let fixture = |machine| {
+        let mut image = common::executable(machine);
+        let image_name = common::EXECUTABLE_NAME.to_owned();
+        image.relro_ranges.push(ImageRange { image_name, addr: 0x2000, size: 0x20 });
+        common::library(machine).merge_into(&mut image).unwrap();
+        image
+    };
+    common::for_each_machine(|machine| {
+        match emit_elf(&fixture(machine), &EmitOptions::default()) {
+            Err(Error::UnsupportedFeature { feature, .. }) => assert!(feature.contains(common::EXECUTABLE_NAME)),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    });
+    let options = EmitOptions { allow_partial_relro: true, ..EmitOptions::default() };
+    common::for_each_round_trip(fixture, &options, |round_trip| {
+        let library_relro_range = round_trip.input.relro_ranges.iter()
+            .find(|relro_range| relro_range.image_name == common::LIBRARY_NAME)
+            .unwrap();
+        assert_eq!(relro_ranges(&round_trip.output), [(library_relro_range.addr + round_trip.base, 0x40)]);
+    });
+}
+
+fn gnu_stack_flags(elf_data: &[u8]) -> u32 {
+    let elf_file = ElfBytes::<ElfAnyEndian>::minimal_parse(elf_data).unwrap();
+    elf_file.segments().unwrap().iter().find(|phdr| phdr.p_type == PT_GNU_STACK).unwrap().p_flags
+}
+
+// The stack is executable if any of the merged images requires it to be, unless that is overridden.
+#[test]
+fn stack_requirements_round_trip() {
+    let fixture = |machine| {
+        let mut image = common::executable(machine);
+        let mut library = common::library(machine);
+        library.stack_requirements[0].executable = true;
+        library.merge_into(&mut image).unwrap();
+        image
+    };
+    for force_non_executable_stack in [false, true] {
+        let options = EmitOptions { force_non_executable_stack, ..EmitOptions::default() };
+        common::for_each_round_trip(fixture, &options, |round_trip| {
+            let elf_data = emit_elf(&round_trip.input, &options).unwrap();
+            let expected_flags = if force_non_executable_stack { PF_R | PF_W } else { PF_R | PF_W | PF_X };
+            assert_eq!(gnu_stack_flags(&elf_data), expected_flags);
+            assert_eq!(round_trip.output.stack_requirements.len(), 1);
+            assert_eq!(round_trip.output.stack_requirements[0].executable, !force_non_executable_stack);
+        });
+    }
+}
+
+// Without PT_GNU_STACK, an image requires an executable stack on x86, but not on newer machines.
+#[test]
+fn missing_stack_requirement() {
+    common::for_each_machine(|machine| {
+        let mut elf_data = emit_elf(&common::library(machine), &EmitOptions::default()).unwrap();
+        let elf_file = ElfBytes::<ElfAnyEndian>::minimal_parse(&elf_data).unwrap();
+        let index = elf_file.segments().unwrap().iter().position(|phdr| phdr.p_type == PT_GNU_STACK).unwrap();
+        let p_type_offset = elf_file.ehdr.e_phoff as usize + index * elf_file.ehdr.e_phentsize as usize;
+        elf_data[p_type_offset..p_type_offset + 4].copy_from_slice(&PT_NULL.to_le_bytes());
+        let parsed = parse_elf::<AnyEndian>(&elf_data, None).unwrap();
+        assert_eq!(parsed.stack_requirements[0].executable, machine == EM_X86_64 || machine == EM_386);
+    });
+}
+
+fn sorted_symbols(image: &Image) -> Vec<Symbol> {
+    let mut symbols = image.symbols.clone();
+    symbols.sort_by_key(|symbol| symbol.versioned_name());
+    symbols
+}
+
+// Definitions keep their version, including hidden ones, and imports keep the version and file they need.
+#[test]
+fn versions_round_trip() {
+    common::for_each_round_trip(common::library, &EmitOptions::default(), |round_trip| {
+        let expected = sorted_symbols(&round_trip.input).into_iter().map(|symbol| {
+            let is_rebased = symbol.value != 0 && symbol.kind != SymbolKind::Tls;
+            Symbol { value: if is_rebased { symbol.value + round_trip.base } else { symbol.value }, ..symbol }
+        }).collect::<Vec<_>>();
+        assert_eq!(sorted_symbols(&round_trip.output), expected);
+    });
+}
+
+// Each kind of relocation is lowered into the relocation type of the machine, and lifted back into the same kind.
+#[test]
+fn relocations_round_trip() {
+    common::for_each_round_trip(common::library, &EmitOptions::default(), |round_trip| {
+        let mut expected = round_trip.input.clone();
+        expected.rebase(round_trip.base).unwrap();
+        let mut relocations = round_trip.output.relocations.clone();
+        relocations.sort_by_key(|relocation| relocation.offset);
+        assert_eq!(relocations, expected.relocations);
+        assert_eq!(round_trip.output.machine, round_trip.machine);
+        assert_eq!(round_trip.output.word_size, round_trip.input.word_size);
+    });
+}
+
+// Without section headers, the number of dynamic symbols is found from whichever hash table is present.
+#[test]
+fn hash_table_symbol_count() {
+    for hash_style in [HashStyle::Gnu, HashStyle::Sysv, HashStyle::Both] {
+        let options = EmitOptions { hash_style, ..EmitOptions::default() };
+        common::for_each_round_trip(common::library, &options, |round_trip| {
+            let mut elf_data = emit_elf(&round_trip.input, &options).unwrap();
+            // Clear `e_shoff`, `e_shnum`, and `e_shstrndx`, which is what `sstrip` does, save for truncating the file.
+            let is_64_bit = round_trip.input.word_size == WordSize::Bits64;
+            elf_data[if is_64_bit { 0x28..0x30 } else { 0x20..0x24 }].fill(0);
+            elf_data[if is_64_bit { 0x3c..0x40 } else { 0x30..0x34 }].fill(0);
+            let without_sections = parse_elf::<AnyEndian>(&elf_data, None).unwrap();
+            assert_eq!(without_sections.symbols.len(), round_trip.input.symbols.len(), "{:?}", hash_style);
+            assert_eq!(without_sections.symbols, round_trip.output.symbols, "{:?}", hash_style);
+        });
+    }
+}
+
+// The lookup tables of the merged images are combined into one, which stays sorted by address.
+#[test]
+fn eh_frame_hdr_round_trip() {
+    common::for_each_round_trip(common::merged, &EmitOptions::default(), |round_trip| {
+        let mut expected_table = Vec::new();
+        for eh_frame_hdr in round_trip.input.eh_frame_hdrs.iter() {
+            expected_table.extend(common::decode_eh_frame_hdr(&round_trip.input, eh_frame_hdr).1);
+        }
+        expected_table.sort();
+        let (expected_eh_frame, _) = common::decode_eh_frame_hdr(&round_trip.input, &round_trip.input.eh_frame_hdrs[0]);
+        assert_eq!(round_trip.output.eh_frame_hdrs.len(), 1);
+        let (eh_frame, table) = common::decode_eh_frame_hdr(&round_trip.output, &round_trip.output.eh_frame_hdrs[0]);
+        assert_eq!(eh_frame, expected_eh_frame + round_trip.base);
+        let table = table.into_iter()
+            .map(|(initial_location, fde)| (initial_location - round_trip.base, fde - round_trip.base))
+            .collect::<Vec<_>>();
+        assert_eq!(table, expected_table);
+    });
+}
+
+fn gnu_build_ids(image: &Image) -> Vec<&Note> {
+    image.notes.iter().filter(|note| note.name == "GNU" && note.note_type == 3).collect()
+}
+
+#[test]
+fn notes_and_properties_round_trip() {
+    common::for_each_round_trip(common::library, &EmitOptions::default(), |round_trip| {
+        assert_eq!(round_trip.output.gnu_properties, round_trip.input.gnu_properties);
+        // The build ID of the input is replaced with one of the output, and kept in a note of our own.
+        let build_ids = gnu_build_ids(&round_trip.output);
+        assert_eq!(build_ids.len(), 1);
+        assert_ne!(build_ids[0].desc, gnu_build_ids(&round_trip.input)[0].desc);
+        let source_build_ids = round_trip.output.notes.iter()
+            .find(|note| note.name.as_bytes() == ELF_NOTE_SUPERLINKER &&
+                note.note_type == NT_SUPERLINKER_SOURCE_BUILD_IDS)
+            .expect("no source build ID note");
+        let mut expected_desc = Vec::new();
+        expected_desc.extend_from_slice(&(common::LIBRARY_NAME.len() as u32).to_le_bytes());
+        expected_desc.extend_from_slice(&20u32.to_le_bytes());
+        expected_desc.extend_from_slice(common::LIBRARY_NAME.as_bytes());
+        expected_desc.extend_from_slice(&[0xab; 20]);
+        expected_desc.resize(expected_desc.len().next_multiple_of(4), 0);
+        assert_eq!(source_build_ids.desc, expected_desc);
+        // The other notes are kept as they are.
+        for note in round_trip.input.notes.iter().filter(|note| note.note_type != 3) {
+            assert!(round_trip.output.notes.contains(note), "missing note {:?}", note);
+        }
+    });
+}
+
+#[test]
+fn notes_and_properties_merge() {
+    common::for_each_round_trip(common::merged, &EmitOptions::default(), |round_trip| {
+        // Only the bits that are set by both images are kept, and properties that must be equal are dropped if
+        // either image lacks them.
+        assert_eq!(round_trip.output.gnu_properties, [GnuProperty {
+            property_type: common::GNU_PROPERTY_UINT32_AND_LO,
+            data: vec![1, 0, 0, 0]
+        }]);
+        // The highest ABI tag is kept.
+        let abi_tags = round_trip.output.notes.iter()
+            .filter(|note| note.name == "GNU" && note.note_type == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(abi_tags.len(), 1);
+        assert_eq!(abi_tags[0].desc, [0, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+    });
+}
+
+fn base_relocations(image: &Image) -> Vec<(u64, i64)> {
+    let mut relocations = image.relocations.iter()
+        .map(|relocation| match relocation.target {
+            RelocationTarget::Base { addend } => (relocation.offset, addend),
+            ref target => panic!("unexpected relocation {:?}", target),
+        })
+        .collect::<Vec<_>>();
+    relocations.sort();
+    relocations
+}
+
+// Encodes the relocated locations as described in the SHT_RELR proposal: an address, followed by bitmaps of which
+// of the following words are relocated.
+fn encode_relr(offsets: &[u64], word_size: u64) -> Vec<u64> {
+    let bitmap_bits = word_size * 8 - 1;
+    let mut entries = Vec::new();
+    let mut index = 0;
+    while index < offsets.len() {
+        entries.push(offsets[index]);
+        let mut next = offsets[index] + word_size;
+        index += 1;
+        loop {
+            let mut bitmap = 0;
+            while let Some(&offset) = offsets.get(index) {
+                if offset < next || offset >= next + bitmap_bits * word_size {
+                    break
+                }
+                bitmap |= 1 << ((offset - next) / word_size);
+                index += 1;
+            }
+            if bitmap == 0 {
+                break
+            }
+            entries.push((bitmap << 1) | 1);
+            next += bitmap_bits * word_size;
+        }
+    }
+    entries
+}
+
+// Rewrites the relative relocations of an emitted image into a RELR table, which is what `ld -z pack-relative-relocs`
+// does. The RELR table takes the place of the REL(A) table, and the addends are moved into the relocated locations.
+fn convert_to_relr(elf_data: &[u8], image: &Image) -> Vec<u8> {
+    let word_size = image.word_size.bytes() as usize;
+    let mut relr_data = elf_data.to_vec();
+    let mut write_word = |offset: usize, word: u64| {
+        relr_data[offset..offset + word_size].copy_from_slice(&common::word_bytes(image.word_size, word));
+    };
+    // The emitter maps the file at the same virtual addresses.
+    let relocations = base_relocations(image);
+    for &(offset, addend) in relocations.iter() {
+        write_word(offset as usize, addend as u64);
+    }
+    let offsets = relocations.iter().map(|&(offset, _)| offset).collect::<Vec<_>>();
+    let relr_entries = encode_relr(&offsets, word_size as u64);
+    assert!(relr_entries.iter().any(|entry| entry & 1 != 0), "no bitmap entries");
+    let elf_file = ElfBytes::<ElfAnyEndian>::minimal_parse(elf_data).unwrap();
+    let dynamic = elf_file.segments().unwrap().iter().find(|phdr| phdr.p_type == PT_DYNAMIC).unwrap();
+    for (index, elf_dyn) in elf_file.dynamic().unwrap().unwrap().iter().enumerate() {
+        let entry_offset = dynamic.p_offset as usize + index * word_size * 2;
+        let (d_tag, d_val) = match (elf_dyn.d_tag, elf_dyn.d_val()) {
+            (DT_RELA | DT_REL, d_val) => {
+                for (entry_index, &entry) in relr_entries.iter().enumerate() {
+                    write_word(d_val as usize + entry_index * word_size, entry);
+                }
+                (DT_RELR, d_val)
+            }
+            (DT_RELASZ | DT_RELSZ, _) => (DT_RELRSZ, (relr_entries.len() * word_size) as u64),
+            (DT_RELAENT | DT_RELENT, _) => (DT_RELRENT, word_size as u64),
+            _ => continue
+        };
+        write_word(entry_offset, d_tag as u64);
+        write_word(entry_offset + word_size, d_val);
+    }
+    relr_data
+}
+
+// Relative relocations packed into a RELR table keep their addends in the relocated locations.
+#[test]
+fn relr_round_trip() {
+    let fixture = |machine| {
+        let mut library = common::library(machine);
+        library.relocations.retain(|relocation| matches!(relocation.target, RelocationTarget::Base { .. }));
+        library
+    };
+    common::for_each_round_trip(fixture, &EmitOptions::default(), |round_trip| {
+        let elf_data = emit_elf(&round_trip.input, &EmitOptions::default()).unwrap();
+        let relr_data = convert_to_relr(&elf_data, &round_trip.output);
+        let with_relr = parse_elf::<AnyEndian>(&relr_data, None).unwrap();
+        assert_eq!(base_relocations(&with_relr), base_relocations(&round_trip.output));
+        assert_eq!(with_relr.relocations.len(), round_trip.input.relocations.len());
+    });
+}
+
+// An implicit addend cannot be kept in the part of a segment that is not backed by the file.
+#[test]
+fn implicit_addend_in_bss_is_rejected() {
+    let library = common::library(EM_386);
+    let mut elf_data = emit_elf(&library, &EmitOptions::default()).unwrap();
+    let elf_file = ElfBytes::<ElfAnyEndian>::minimal_parse(&elf_data).unwrap();
+    let relocated_addr = parse_elf::<AnyEndian>(&elf_data, None).unwrap().relocations.iter()
+        .map(|relocation| relocation.offset)
+        .max()
+        .unwrap();
+    let (index, _) = elf_file.segments().unwrap().iter().enumerate()
+        .find(|(_, phdr)| {
+            phdr.p_type == PT_LOAD && (phdr.p_vaddr..phdr.p_vaddr + phdr.p_memsz).contains(&relocated_addr)
+        })
+        .unwrap();
+    // Leave the relocated location out of the part of the segment that is read from the file.
+    let p_filesz_offset = elf_file.ehdr.e_phoff as usize + index * elf_file.ehdr.e_phentsize as usize + 16;
+    elf_data[p_filesz_offset..p_filesz_offset + 4].copy_from_slice(&0u32.to_le_bytes());
+    match parse_elf::<AnyEndian>(&elf_data, None) {
+        Err(Error::InvalidRelocation { reason, .. }) => assert_eq!(reason, "relocated location out of bounds"),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}